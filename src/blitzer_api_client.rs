@@ -1,31 +1,243 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
 use anyhow::bail;
-use crate::model::{ApiResponse, BlitzerClientRequestParams};
-use reqwest_middleware::ClientBuilder;
+use reqwest::Proxy;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+use crate::configuration;
+use crate::configuration::{ClusterConfiguration, TileConfiguration};
+use crate::model::{ApiResponse, BlitzerClientRequestParams, LocationBox, LocationType, Poi};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 
-const BASE_URL: &str = "https://cdn2.atudo.net/api/4.0/pois.php?";
+/// Client of the blitzer.de api. It is created once from the settings and its connections are reused by all requests.
+#[derive(Clone)]
+pub struct ApiClient {
+    client: ClientWithMiddleware,
+    endpoint: String,
+    zoom_level: i32,
+    tile_configuration: TileConfiguration,
+    cluster_configuration: ClusterConfiguration,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl ApiClient {
+    pub async fn from_configuration() -> anyhow::Result<ApiClient> {
+        let api_configuration = configuration::get_api_configuration().await;
+
+        let mut client = reqwest::Client::builder()
+            .timeout(api_configuration.timeout.to_std()?)
+            .user_agent(api_configuration.user_agent);
+        if let Some(proxy) = api_configuration.proxy {
+            client = client.proxy(Proxy::all(proxy)?);
+        }
+
+        let retry_policy = ExponentialBackoff::builder()
+            .retry_bounds(api_configuration.min_backoff.to_std()?, api_configuration.max_backoff.to_std()?)
+            .build_with_max_retries(api_configuration.retries);
+        let client = ClientBuilder::new(client.build()?)
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+
+        let tile_configuration = configuration::get_tile_configuration().await;
+        Ok(ApiClient {
+            client,
+            endpoint: api_configuration.endpoint,
+            zoom_level: api_configuration.zoom_level,
+            rate_limiter: Arc::new(RateLimiter::new(tile_configuration.requests_per_second)),
+            tile_configuration,
+            cluster_configuration: configuration::get_cluster_configuration().await,
+        })
+    }
+
+    /// Requests the pois of a location box. Large boxes are split into tiles, which are requested concurrently and
+    /// merged without the pois which were found in several tiles.
+    pub async fn get_pois(&self, types: &[LocationType], location_box: LocationBox) -> anyhow::Result<Vec<Poi>> {
+        let tiles = location_box.tiles(self.tile_configuration.max_size);
+        let tile_count = tiles.len();
+        if tile_count > 1 {
+            println!("Splitting locationBox {} into {} tiles", location_box, tile_count);
+        }
+
+        let semaphore = Arc::new(Semaphore::new(self.tile_configuration.concurrency));
+        let mut requests = JoinSet::new();
+        for (index, tile) in tiles.into_iter().enumerate() {
+            let tile_params = BlitzerClientRequestParams {
+                zoom_level: self.zoom_level,
+                types: types.to_vec(),
+                location_box: tile,
+            };
+            let api_client = self.clone();
+            let semaphore = semaphore.clone();
+            requests.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("Semaphore should not be closed");
+                let started = Instant::now();
+                let location_box = tile_params.location_box.to_string();
+                let result = api_client.get_tile_pois(tile_params).await;
+                (index, location_box, started.elapsed(), result)
+            });
+        }
+
+        let mut tile_results = Vec::with_capacity(tile_count);
+        while let Some(tile_result) = requests.join_next().await {
+            tile_results.push(tile_result?);
+        }
+        tile_results.sort_by_key(|(index, ..)| *index);
+
+        let mut seen_backend_ids = HashSet::new();
+        let mut pois = Vec::new();
+        for (index, location_box, duration, result) in tile_results {
+            let (tile_pois, request_count) = result?;
+            let poi_count = tile_pois.len();
+            let mut cluster_count = 0;
+            let mut duplicate_count = 0;
+            for poi in tile_pois {
+                match &poi {
+                    Poi::Detailed(detailed_poi) if !seen_backend_ids.insert(detailed_poi.backend.clone()) => {
+                        duplicate_count += 1;
+                        continue;
+                    }
+                    Poi::Detailed(_) => {}
+                    Poi::Cluster(_) => cluster_count += 1,
+                }
+                pois.push(poi);
+            }
+
+            if tile_count > 1 {
+                println!(
+                    "Tile {}/{} {}: {} pois ({} duplicates), {} unresolved clusters, {} requests in {} ms",
+                    index + 1,
+                    tile_count,
+                    location_box,
+                    poi_count - cluster_count,
+                    duplicate_count,
+                    cluster_count,
+                    request_count,
+                    duration.as_millis()
+                );
+            }
+        }
+
+        Ok(pois)
+    }
 
-pub async fn get_blitzer_api_result(
-    client_params: &BlitzerClientRequestParams,
-) -> anyhow::Result<ApiResponse> {
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    let reqwest_client = ClientBuilder::new(reqwest::Client::new())
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
+    /// Requests the pois of a tile and resolves the clusters in it. The quarters of the tile which contain
+    /// clusters are requested again with a higher zoom, until only detailed pois remain or the depth or request
+    /// budget is used up. Clusters which could not be resolved are returned as they are. Returns the pois with
+    /// the number of requests.
+    async fn get_tile_pois(&self, client_params: BlitzerClientRequestParams) -> anyhow::Result<(Vec<Poi>, u32)> {
+        let mut requests = VecDeque::from([(client_params, 0)]);
+        let mut request_count = 0;
+        let mut pois = Vec::new();
 
-    let response = match reqwest_client.get(BASE_URL).query(&client_params.as_query_parameter()).send().await {
-        Ok(response) => response,
-        Err(err) => bail!("Failed to get blitzer response: {:?}", err),
-    };
+        while let Some((client_params, depth)) = requests.pop_front() {
+            self.rate_limiter.wait().await;
+            let api_response = self.get_api_response(&client_params).await?;
+            request_count += 1;
+            for grid_cell in &api_response.grid {
+                println!("The api sent a grid cell for {}: {}", client_params.location_box, grid_cell);
+            }
+            for info in &api_response.infos {
+                println!(
+                    "The api sent an info for {}: {} {:?}",
+                    client_params.location_box,
+                    info.desc.as_deref().unwrap_or_default(),
+                    info.unknown_fields
+                );
+            }
+
+            let (clusters, detailed_pois): (Vec<_>, Vec<_>) =
+                api_response.pois.into_iter().partition(|poi| matches!(poi, Poi::Cluster(_)));
+            pois.extend(detailed_pois);
+            if clusters.is_empty() {
+                continue;
+            }
+
+            let mut quarter_indexes = clusters
+                .iter()
+                .filter_map(|poi| match poi {
+                    Poi::Cluster(cluster_poi) => Some(client_params.location_box.quarter_index(cluster_poi.lat, cluster_poi.lng)),
+                    Poi::Detailed(_) => None,
+                })
+                .collect::<Vec<_>>();
+            quarter_indexes.sort();
+            quarter_indexes.dedup();
+
+            let planned_requests = request_count as usize + requests.len() + quarter_indexes.len();
+            if depth >= self.cluster_configuration.max_depth
+                || planned_requests > self.cluster_configuration.max_requests as usize
+            {
+                println!(
+                    "Found {} clusters in {}, but the depth or request budget is used up.. skipped",
+                    clusters.len(),
+                    client_params.location_box
+                );
+                pois.extend(clusters);
+                continue;
+            }
+
+            println!(
+                "Found {} clusters in {}.. requesting {} smaller boxes",
+                clusters.len(),
+                client_params.location_box,
+                quarter_indexes.len()
+            );
+            let quarters = client_params.location_box.quarters();
+            for quarter_index in quarter_indexes {
+                let quarter_params = BlitzerClientRequestParams {
+                    zoom_level: client_params.zoom_level + 1,
+                    types: client_params.types.clone(),
+                    location_box: quarters[quarter_index].clone(),
+                };
+                requests.push_back((quarter_params, depth + 1));
+            }
+        }
+
+        Ok((pois, request_count))
+    }
+
+    async fn get_api_response(&self, client_params: &BlitzerClientRequestParams) -> anyhow::Result<ApiResponse> {
+        let response = match self.client.get(&self.endpoint).query(&client_params.as_query_parameter()).send().await {
+            Ok(response) => response,
+            Err(err) => bail!("Failed to get blitzer response: {:?}", err),
+        };
+
+        if !response.status().is_success() {
+            bail!("Request failed with status: {}", response.status());
+        }
+
+        match response.json::<ApiResponse>().await {
+            Ok(response) => Ok(response),
+            Err(error) => {
+                bail!("Failed to parse response: {}", error)
+            },
+        }
+    }
+}
+
+/// Spaces the requests to the api evenly, it is shared by all requests of the client.
+struct RateLimiter {
+    interval: Duration,
+    next_request: Mutex<Instant>,
+}
 
-    if !response.status().is_success() {
-        bail!("Request failed with status: {}", response.status());
+impl RateLimiter {
+    fn new(requests_per_second: f64) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next_request: Mutex::new(Instant::now()),
+        }
     }
 
-    match response.json::<ApiResponse>().await {
-        Ok(response) => Ok(response),
-        Err(error) => {
-            bail!("Failed to parse response: {}", error)
-        },
+    async fn wait(&self) {
+        let request_at = {
+            let mut next_request = self.next_request.lock().await;
+            let request_at = (*next_request).max(Instant::now());
+            *next_request = request_at + self.interval;
+            request_at
+        };
+        sleep_until(request_at).await;
     }
 }
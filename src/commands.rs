@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use chrono::{Duration, Local};
+use teloxide::dispatching::{Dispatcher, HandlerExt, UpdateFilterExt};
+use teloxide::payloads::SendMessageSetters;
+use teloxide::prelude::{dptree, Message, Requester, ResponseResult, Update};
+use teloxide::types::ReplyParameters;
+use teloxide::utils::command::BotCommands;
+use teloxide::Bot;
+use tokio::sync::Mutex;
+use crate::configuration;
+use crate::configuration::RegionArea;
+use crate::database::{Storage, StorageError, StorageResult};
+use crate::model::{Coordinate, LocationType};
+use crate::route::{bearing_degrees, distance_meters};
+
+const MAX_MESSAGE_LENGTH: usize = 4000;
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "These commands are supported:")]
+enum Command {
+    #[command(description = "show this help.")]
+    Help,
+    #[command(description = "show the number of active pois per region.")]
+    Status,
+    #[command(description = "list all active pois.")]
+    List,
+    #[command(description = "list active pois near a coordinate, e.g. /near 53.55,9.99 (or just share a location)")]
+    Near(String),
+    #[command(description = "mute new notifications for this chat, e.g. /mute 2h or /mute off")]
+    Mute(String),
+    #[command(description = "show the watched poi types per region.")]
+    Types,
+    #[command(description = "show the watched regions.")]
+    Region,
+}
+
+pub type SharedStorage = Arc<Mutex<Box<dyn Storage>>>;
+
+// The bot answers only the configured chats, as the commands show the pois of all regions
+#[derive(Clone)]
+struct AllowedChats(Arc<HashSet<String>>);
+
+pub async fn dispatcher(
+    bot: Bot,
+    database: SharedStorage,
+) -> Dispatcher<Bot, teloxide::RequestError, teloxide::dispatching::DefaultKey> {
+    let mut allowed_chats = HashSet::from([configuration::get_telegram_bot_configuration().await.chat_id]);
+    allowed_chats.extend(configuration::get_regions().await.into_iter().map(|region| region.chat_id));
+
+    let handler = Update::filter_message()
+        .chain(dptree::filter(is_allowed_chat))
+        .branch(dptree::entry().filter_command::<Command>().endpoint(answer))
+        .branch(dptree::filter(|message: Message| message.location().is_some()).endpoint(answer_location));
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![database, AllowedChats(Arc::new(allowed_chats))])
+        .build()
+}
+
+fn is_allowed_chat(message: Message, allowed_chats: AllowedChats) -> bool {
+    let chat_id = message.chat.id.to_string();
+    let is_allowed = allowed_chats.0.contains(&chat_id);
+    if !is_allowed {
+        println!("Ignoring message of unknown chat {}", chat_id);
+    }
+    is_allowed
+}
+
+async fn answer(bot: Bot, message: Message, command: Command, database: SharedStorage) -> ResponseResult<()> {
+    let text = match command {
+        Command::Help => Ok(Command::descriptions().to_string()),
+        Command::Status => status(&database, &message).await,
+        Command::List => list(&database).await,
+        Command::Near(coordinate) => match parse_coordinate(&coordinate) {
+            Some(coordinate) => near(&database, &coordinate).await,
+            None => Ok(String::from("Please send a coordinate like /near 53.55,9.99")),
+        },
+        Command::Mute(duration) => mute(&database, &message, &duration).await,
+        Command::Types => types(&database).await,
+        Command::Region => Ok(regions().await),
+    }
+    .unwrap_or_else(database_error);
+
+    bot.send_message(message.chat.id, truncate(text)).await?;
+    Ok(())
+}
+
+async fn answer_location(bot: Bot, message: Message, database: SharedStorage) -> ResponseResult<()> {
+    let Some(location) = message.location() else {
+        return Ok(());
+    };
+
+    let coordinate = Coordinate {
+        lat: location.latitude,
+        lng: location.longitude,
+    };
+    let text = near(&database, &coordinate).await.unwrap_or_else(database_error);
+    bot.send_message(message.chat.id, truncate(text))
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(())
+}
+
+fn database_error(error: StorageError) -> String {
+    eprintln!("Failed to answer command: {}", error);
+    String::from("The database is not available right now, please try again later")
+}
+
+// Telegram rejects messages with more than 4096 characters
+fn truncate(text: String) -> String {
+    match text.char_indices().nth(MAX_MESSAGE_LENGTH) {
+        Some((index, _)) => format!("{}\n...", &text[..index]),
+        None => text,
+    }
+}
+
+async fn status(database: &SharedStorage, message: &Message) -> StorageResult<String> {
+    let regions = configuration::get_regions().await;
+    let mut database = database.lock().await;
+    let active_pois = database.get_active_pois()?;
+
+    let mut lines = vec![format!("{} active pois", active_pois.len())];
+    for region in regions {
+        let count = active_pois.iter().filter(|poi| poi.region == region.name).count();
+        lines.push(format!("{}: {}", region.name, count));
+    }
+
+    if let Some(muted_until) = database.get_muted_until(&message.chat.id.to_string())? {
+        if muted_until > Local::now().naive_local() {
+            lines.push(format!("Notifications are muted until {}", muted_until.format("%d.%m.%Y %H:%M")));
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
+
+async fn list(database: &SharedStorage) -> StorageResult<String> {
+    let language = configuration::get_message_configuration().await.language;
+    let active_pois = database.lock().await.get_active_pois()?;
+    if active_pois.is_empty() {
+        return Ok(String::from("There are no active pois"));
+    }
+
+    Ok(active_pois
+        .iter()
+        .map(|poi| {
+            format!(
+                "[{}] {}, {} {} (type: {}, max speed: {}, since {}, BackendId: {})",
+                poi.region,
+                poi.address_street,
+                poi.address_zip_code,
+                poi.address_city,
+                match LocationType::from_code(&poi.poi_type) {
+                    LocationType::Unknown(_) => &poi.poi_type,
+                    location_type => location_type.display_name(language),
+                },
+                poi.vmax.map(|vmax| vmax.to_string()).unwrap_or_else(|| String::from("unknown")),
+                poi.first_seen.format("%d.%m.%Y %H:%M"),
+                poi.backend_id
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+async fn near(database: &SharedStorage, coordinate: &Coordinate) -> StorageResult<String> {
+    let radius = configuration::get_near_radius().await;
+
+    let mut active_pois: Vec<_> = database
+        .lock()
+        .await
+        .get_active_pois()?
+        .into_iter()
+        .map(|poi| {
+            let poi_coordinate = Coordinate { lat: poi.lat, lng: poi.lng };
+            let distance = distance_meters(coordinate, &poi_coordinate);
+            let bearing = bearing_degrees(coordinate, &poi_coordinate);
+            (distance, bearing, poi)
+        })
+        .filter(|(distance, _, _)| *distance <= radius)
+        .collect();
+    active_pois.sort_by(|(first, _, _), (second, _, _)| first.total_cmp(second));
+
+    if active_pois.is_empty() {
+        return Ok(format!("There are no active pois within {} km", radius / 1000.0));
+    }
+
+    Ok(active_pois
+        .iter()
+        .map(|(distance, bearing, poi)| {
+            format!(
+                "{:.1} km {} ({:.0}°): {}, {} {} (max speed: {})",
+                distance / 1000.0,
+                compass_direction(*bearing),
+                bearing,
+                poi.address_street,
+                poi.address_zip_code,
+                poi.address_city,
+                poi.vmax.map(|vmax| vmax.to_string()).unwrap_or_else(|| String::from("unknown"))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn compass_direction(bearing: f64) -> &'static str {
+    const DIRECTIONS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    DIRECTIONS[((bearing + 22.5) / 45.0) as usize % DIRECTIONS.len()]
+}
+
+async fn mute(database: &SharedStorage, message: &Message, duration: &str) -> StorageResult<String> {
+    let chat_id = message.chat.id.to_string();
+
+    if matches!(duration.trim(), "off" | "0") {
+        database.lock().await.set_muted_until(&chat_id, None)?;
+        return Ok(String::from("Notifications are active again"));
+    }
+
+    let muted_until = parse_duration(duration).and_then(|duration| Local::now().naive_local().checked_add_signed(duration));
+    let Some(muted_until) = muted_until else {
+        return Ok(String::from("Invalid duration, please send a duration like /mute 30m, /mute 2h, /mute 1d or /mute off"));
+    };
+
+    database.lock().await.set_muted_until(&chat_id, Some(muted_until))?;
+    Ok(format!("New pois are not announced until {}", muted_until.format("%d.%m.%Y %H:%M")))
+}
+
+async fn types(database: &SharedStorage) -> StorageResult<String> {
+    let language = configuration::get_message_configuration().await.language;
+
+    let mut lines = configuration::get_regions()
+        .await
+        .iter()
+        .map(|region| {
+            let types = region
+                .types
+                .iter()
+                .map(|location_type| {
+                    format!(
+                        "{} {} ({})",
+                        location_type.metadata().icon,
+                        location_type.display_name(language),
+                        location_type.code()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}: {}", region.name, types)
+        })
+        .collect::<Vec<_>>();
+
+    let unknown_types = database
+        .lock()
+        .await
+        .get_poi_types()?
+        .into_iter()
+        .filter(|poi_type| matches!(LocationType::from_code(&poi_type.code), LocationType::Unknown(_)))
+        .map(|poi_type| {
+            format!(
+                "{} (first seen {}, last seen {}, BackendId: {})",
+                poi_type.code,
+                poi_type.first_seen.format("%d.%m.%Y %H:%M"),
+                poi_type.last_seen.format("%d.%m.%Y %H:%M"),
+                poi_type.backend_id
+            )
+        })
+        .collect::<Vec<_>>();
+    if !unknown_types.is_empty() {
+        lines.push(format!("Unknown types sent by the api:\n{}", unknown_types.join("\n")));
+    }
+
+    Ok(lines.join("\n\n"))
+}
+
+async fn regions() -> String {
+    configuration::get_regions()
+        .await
+        .iter()
+        .map(|region| match &region.area {
+            RegionArea::Box(location_box) => format!("{}: box {}", region.name, location_box),
+            RegionArea::Route(route) => {
+                format!("{}: route covered by {} boxes", region.name, route.location_boxes().len())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_coordinate(coordinate: &str) -> Option<Coordinate> {
+    let (lat, lng) = coordinate.split_once(',')?;
+
+    Some(Coordinate {
+        lat: lat.trim().parse().ok()?,
+        lng: lng.trim().parse().ok()?,
+    })
+}
+
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let unit_index = duration.find(|character: char| !character.is_ascii_digit())?;
+    let (value, unit) = duration.split_at(unit_index);
+    let value: i64 = value.parse().ok()?;
+
+    match unit.trim() {
+        "m" | "min" => Duration::try_minutes(value),
+        "h" => Duration::try_hours(value),
+        "d" => Duration::try_days(value),
+        _ => None,
+    }
+}
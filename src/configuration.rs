@@ -1,5 +1,10 @@
-use crate::model::LocationBox;
+use crate::model::{Category, Coordinate, DetailedPoi, Language, LocationBox, LocationType};
+use crate::route::Route;
+use chrono::{Duration, Local};
 use config::Config;
+use serde::Deserialize;
+use std::collections::HashMap;
+use strum::IntoEnumIterator;
 use std::sync::OnceLock;
 use tokio::sync::RwLock;
 
@@ -17,21 +22,149 @@ fn init_config() -> Config {
         .unwrap()
 }
 
-pub async fn get_location_box() -> LocationBox {
-    let lat_min = get_float("locations.first.lat").await;
-    let lng_min = get_float("locations.first.lng").await;
-    let lat_max = get_float("locations.second.lat").await;
-    let lng_max = get_float("locations.second.lng").await;
+pub async fn get_regions() -> Vec<Region> {
+    let default_chat_id = get_string("telegram.chat_id").await;
 
-    LocationBox {
-        lat_min,
-        lng_min,
-        lat_max,
-        lng_max,
+    let region_configurations = {
+        let config = config().read().await;
+        config.get::<Vec<RegionConfiguration>>("regions")
+    };
+
+    let region_configurations = match region_configurations {
+        Ok(region_configurations) => region_configurations,
+        // Fallback for settings files which still use the single `[locations]` box
+        Err(config::ConfigError::NotFound(_)) => vec![RegionConfiguration {
+            name: String::from("default"),
+            first: Some(get_coordinate("locations.first").await),
+            second: Some(get_coordinate("locations.second").await),
+            route: None,
+            types: None,
+            categories: None,
+            filter: None,
+            chat_id: None,
+            notifiers: None,
+        }],
+        Err(error) => panic!("Configuration should have a valid list of regions: {error}"),
+    };
+
+    let default_filter = {
+        let config = config().read().await;
+        config.get::<PoiFilter>("filter")
+    };
+    let default_filter = match default_filter {
+        Ok(default_filter) => default_filter,
+        Err(config::ConfigError::NotFound(_)) => PoiFilter::default(),
+        Err(error) => panic!("Configuration should have a valid filter: {error}"),
+    };
+
+    region_configurations
+        .into_iter()
+        .map(|region| Region {
+            area: region_area(&region),
+            types: region.types.unwrap_or_else(|| {
+                LocationType::iter()
+                    .filter(|location_type: &LocationType| match &region.categories {
+                        Some(categories) => categories.contains(&location_type.metadata().category),
+                        None => location_type.is_default(),
+                    })
+                    .collect()
+            }),
+            filter: region.filter.unwrap_or_else(|| default_filter.clone()),
+            chat_id: region.chat_id.unwrap_or_else(|| default_chat_id.clone()),
+            notifiers: region.notifiers.unwrap_or_else(|| vec![String::from(TELEGRAM_NOTIFIER)]),
+            name: region.name,
+        })
+        .collect()
+}
+
+/// The notifiers configured in the `[notifiers.<name>]` tables. Telegram is always available as `telegram`.
+pub async fn get_notifier_configurations() -> HashMap<String, NotifierConfiguration> {
+    let notifier_configurations = {
+        let config = config().read().await;
+        config.get::<HashMap<String, NotifierConfiguration>>("notifiers")
+    };
+
+    let mut notifier_configurations = match notifier_configurations {
+        Ok(notifier_configurations) => notifier_configurations,
+        Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+        Err(error) => panic!("Configuration should have valid notifiers: {error}"),
+    };
+    notifier_configurations
+        .entry(String::from(TELEGRAM_NOTIFIER))
+        .or_insert(NotifierConfiguration::Telegram {});
+
+    notifier_configurations
+}
+
+pub async fn get_error_notifiers() -> Vec<String> {
+    let error_notifiers = {
+        let config = config().read().await;
+        config.get::<Vec<String>>("error_notifiers")
+    };
+
+    match error_notifiers {
+        Ok(error_notifiers) => error_notifiers,
+        Err(config::ConfigError::NotFound(_)) => vec![String::from(TELEGRAM_NOTIFIER)],
+        Err(error) => panic!("Configuration should have a list of error_notifiers: {error}"),
     }
 }
 
-pub async fn get_mysql_connection_uri() -> String {
+pub const TELEGRAM_NOTIFIER: &str = "telegram";
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum NotifierConfiguration {
+    Telegram {},
+    Matrix {
+        homeserver: String,
+        access_token: String,
+        room_id: String,
+    },
+    Ntfy {
+        url: String,
+        token: Option<String>,
+    },
+    Gotify {
+        url: String,
+        token: String,
+        priority: Option<i32>,
+    },
+    Webhook {
+        url: String,
+        headers: Option<HashMap<String, String>>,
+        secret: Option<String>,
+        retries: Option<u32>,
+    },
+    Email {
+        host: String,
+        port: Option<u16>,
+        username: String,
+        password: String,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+pub async fn get_database_configuration() -> DatabaseConfiguration {
+    let kind = get_optional_string("database.kind").await.unwrap_or_else(|| String::from("mysql"));
+
+    match kind.as_str() {
+        "mysql" => DatabaseConfiguration::Mysql {
+            connection_uri: get_mysql_connection_uri().await,
+        },
+        "sqlite" => DatabaseConfiguration::Sqlite {
+            path: get_optional_string("database.path").await.unwrap_or_else(|| String::from("blitzer.sqlite")),
+        },
+        _ => panic!("Configuration should have a database kind of mysql or sqlite, got {kind}"),
+    }
+}
+
+pub enum DatabaseConfiguration {
+    Mysql { connection_uri: String },
+    Sqlite { path: String },
+}
+
+async fn get_mysql_connection_uri() -> String {
     format!(
         "mysql://{}:{}@{}:{}/{}",
         get_string("database.username").await,
@@ -49,11 +182,364 @@ pub async fn get_telegram_bot_configuration() -> TelegramBotConfiguration {
     }
 }
 
+pub async fn get_daemon_configuration() -> DaemonConfiguration {
+    DaemonConfiguration {
+        interval: daemon_interval("daemon.interval", get_optional_int("daemon.interval").await.unwrap_or(3600)),
+        night_interval: get_optional_int("daemon.night_interval")
+            .await
+            .map(|interval| daemon_interval("daemon.night_interval", interval)),
+        weekend_interval: get_optional_int("daemon.weekend_interval")
+            .await
+            .map(|interval| daemon_interval("daemon.weekend_interval", interval)),
+        night_start: daemon_hour("daemon.night_start", get_optional_int("daemon.night_start").await.unwrap_or(22)),
+        night_end: daemon_hour("daemon.night_end", get_optional_int("daemon.night_end").await.unwrap_or(6)),
+    }
+}
+
+fn daemon_interval(key: &str, interval: i64) -> u64 {
+    if interval < 1 {
+        panic!("Configuration should have an interval of at least 1 second on {key}, got {interval}");
+    }
+    interval as u64
+}
+
+fn daemon_hour(key: &str, hour: i64) -> u32 {
+    if !(0..=23).contains(&hour) {
+        panic!("Configuration should have an hour between 0 and 23 on {key}, got {hour}");
+    }
+    hour as u32
+}
+
+pub struct DaemonConfiguration {
+    pub interval: u64,
+    pub night_interval: Option<u64>,
+    pub weekend_interval: Option<u64>,
+    pub night_start: u32,
+    pub night_end: u32,
+}
+
+pub async fn get_grace_configuration() -> GraceConfiguration {
+    let missed_runs = get_optional_int("grace.missed_runs").await.map(|missed_runs| {
+        u32::try_from(missed_runs)
+            .unwrap_or_else(|_| panic!("Configuration should have a grace.missed_runs of at least 0, got {missed_runs}"))
+    });
+    let missed_minutes = get_optional_int("grace.missed_minutes").await;
+    if let Some(missed_minutes) =
+        missed_minutes.filter(|missed_minutes| *missed_minutes < 0 || Duration::try_minutes(*missed_minutes).is_none())
+    {
+        panic!("Configuration should have a grace.missed_minutes of at least 0, got {missed_minutes}");
+    }
+
+    GraceConfiguration {
+        missed_runs,
+        missed_minutes,
+    }
+}
+
+pub struct GraceConfiguration {
+    pub missed_runs: Option<u32>,
+    pub missed_minutes: Option<i64>,
+}
+
+impl GraceConfiguration {
+    pub fn is_gone(&self, missed_runs: u32, missed_since: Duration) -> bool {
+        match (self.missed_runs, self.missed_minutes) {
+            (None, None) => missed_runs >= 1,
+            (runs, minutes) => {
+                runs.is_some_and(|runs| missed_runs >= runs)
+                    || minutes.is_some_and(|minutes| missed_since >= Duration::minutes(minutes))
+            }
+        }
+    }
+}
+
+pub async fn get_cluster_configuration() -> ClusterConfiguration {
+    ClusterConfiguration {
+        max_depth: get_optional_int("clusters.max_depth").await.map_or(3, |max_depth| max_depth as u32),
+        max_requests: get_optional_int("clusters.max_requests").await.map_or(20, |max_requests| max_requests as u32),
+    }
+}
+
+pub async fn get_api_configuration() -> ApiConfiguration {
+    ApiConfiguration {
+        endpoint: get_optional_string("api.endpoint")
+            .await
+            .unwrap_or_else(|| String::from("https://cdn2.atudo.net/api/4.0/pois.php")),
+        zoom_level: get_optional_int("api.zoom").await.map_or(5, |zoom_level| zoom_level as i32),
+        timeout: Duration::seconds(get_optional_int("api.timeout").await.unwrap_or(30)),
+        user_agent: get_optional_string("api.user_agent")
+            .await
+            .unwrap_or_else(|| format!("BlitzerNotifier/{}", env!("CARGO_PKG_VERSION"))),
+        retries: get_optional_int("api.retries").await.map_or(3, |retries| retries as u32),
+        min_backoff: Duration::seconds(get_optional_int("api.min_backoff").await.unwrap_or(1)),
+        max_backoff: Duration::seconds(get_optional_int("api.max_backoff").await.unwrap_or(30)),
+        proxy: get_optional_string("api.proxy").await,
+    }
+}
+
+/// Connection of the blitzer.de api, e.g. to use a mirror or a local stand-in.
+pub struct ApiConfiguration {
+    pub endpoint: String,
+    pub zoom_level: i32,
+    pub timeout: Duration,
+    pub user_agent: String,
+    pub retries: u32,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub proxy: Option<String>,
+}
+
+pub async fn get_tile_configuration() -> TileConfiguration {
+    let max_size = get_optional_float("tiles.max_size").await.unwrap_or(50.0);
+    if max_size.is_nan() || max_size <= 0.0 {
+        panic!("Configuration should have a tiles.max_size greater than 0, got {max_size}");
+    }
+    let concurrency = get_optional_int("tiles.concurrency").await.unwrap_or(4);
+    if concurrency <= 0 {
+        panic!("Configuration should have a tiles.concurrency greater than 0, got {concurrency}");
+    }
+    let requests_per_second = get_optional_float("tiles.requests_per_second").await.unwrap_or(2.0);
+    if requests_per_second.is_nan() || requests_per_second <= 0.0 {
+        panic!("Configuration should have a tiles.requests_per_second greater than 0, got {requests_per_second}");
+    }
+
+    TileConfiguration {
+        max_size,
+        concurrency: concurrency as usize,
+        requests_per_second,
+    }
+}
+
+/// Splits large location boxes into tiles of at most `max_size` km, which are requested concurrently.
+#[derive(Clone)]
+pub struct TileConfiguration {
+    pub max_size: f64,
+    pub concurrency: usize,
+    pub requests_per_second: f64,
+}
+
+/// Limits the requests which resolve the clusters of a location box.
+#[derive(Clone)]
+pub struct ClusterConfiguration {
+    pub max_depth: u32,
+    pub max_requests: u32,
+}
+
+pub async fn get_message_configuration() -> MessageConfiguration {
+    let language = get_optional_string("messages.language").await.unwrap_or_else(|| String::from("en"));
+
+    MessageConfiguration {
+        language: match language.as_str() {
+            "en" => Language::English,
+            "de" => Language::German,
+            _ => panic!("Configuration should have a messages language of en or de, got {language}"),
+        },
+        new_poi: get_optional_string("messages.new_poi").await,
+        reappeared_poi: get_optional_string("messages.reappeared_poi").await,
+        changed_poi: get_optional_string("messages.changed_poi").await,
+        gone_poi: get_optional_string("messages.gone_poi").await,
+    }
+}
+
+pub struct MessageConfiguration {
+    pub language: Language,
+    pub new_poi: Option<String>,
+    pub reappeared_poi: Option<String>,
+    pub changed_poi: Option<String>,
+    pub gone_poi: Option<String>,
+}
+
+pub async fn get_telegram_format() -> TelegramFormat {
+    let format = get_optional_string("telegram.format").await.unwrap_or_else(|| String::from("text"));
+
+    match format.as_str() {
+        "text" => TelegramFormat::Text,
+        "html" => TelegramFormat::Html,
+        "venue" => TelegramFormat::Venue,
+        _ => panic!("Configuration should have a telegram format of text, html or venue, got {format}"),
+    }
+}
+
+/// How a poi is sent to telegram: a text with a separate location, a single html message
+/// with map links or a single venue.
+#[derive(Clone, Copy)]
+pub enum TelegramFormat {
+    Text,
+    Html,
+    Venue,
+}
+
+/// What happens if a single poi can not be notified or stored.
+#[derive(Clone, Copy)]
+pub enum FailurePolicy {
+    // Reports the error and retries the poi in the next run
+    Skip,
+    // Stops the run, which is retried as a whole
+    Abort,
+}
+
+pub async fn get_failure_policy() -> FailurePolicy {
+    match get_optional_string("failure_policy").await.as_deref() {
+        None | Some("skip") => FailurePolicy::Skip,
+        Some("abort") => FailurePolicy::Abort,
+        Some(failure_policy) => {
+            panic!("Configuration should have a failure_policy of skip or abort, got {failure_policy}")
+        }
+    }
+}
+
+pub async fn get_near_radius() -> f64 {
+    get_optional_float("telegram.near_radius").await.unwrap_or(10_000.0)
+}
+
 pub struct TelegramBotConfiguration {
     pub token: String,
     pub chat_id: String,
 }
 
+pub struct Region {
+    pub name: String,
+    pub area: RegionArea,
+    pub types: Vec<LocationType>,
+    pub filter: PoiFilter,
+    pub chat_id: String,
+    pub notifiers: Vec<String>,
+}
+
+/// Rules for the pois of a region, either from `[regions.filter]` or the top-level `[filter]`.
+#[derive(Deserialize, Default, Clone)]
+pub struct PoiFilter {
+    include_categories: Option<Vec<Category>>,
+    #[serde(default)]
+    exclude_categories: Vec<Category>,
+    // Pois without a speed limit are kept
+    vmax_below: Option<i32>,
+    max_confirm_age_days: Option<i64>,
+    #[serde(default)]
+    ignored_backend_ids: Vec<String>,
+}
+
+impl PoiFilter {
+    /// The reason why the poi is not notified, if any rule rejects it.
+    pub fn rejects(&self, poi: &DetailedPoi) -> Option<String> {
+        if self.ignored_backend_ids.contains(&poi.backend) {
+            return Some(String::from("ignored backend id"));
+        }
+
+        let category = LocationType::from_code(&poi.poi_type).metadata().category;
+        if let Some(include_categories) = &self.include_categories {
+            if !include_categories.contains(&category) {
+                return Some(format!("category {category:?} is not included"));
+            }
+        }
+        if self.exclude_categories.contains(&category) {
+            return Some(format!("category {category:?} is excluded"));
+        }
+
+        if let (Some(vmax_below), Some(vmax)) = (self.vmax_below, poi.vmax) {
+            if vmax >= vmax_below {
+                return Some(format!("max speed {vmax} is not below {vmax_below}"));
+            }
+        }
+
+        // Pois which were never confirmed are as old as their creation
+        if let (Some(max_confirm_age_days), Some(confirm_date)) =
+            (self.max_confirm_age_days, poi.confirm_date.or(poi.create_date))
+        {
+            if Local::now().naive_local() - confirm_date > Duration::days(max_confirm_age_days) {
+                return Some(format!("last confirmed at {confirm_date}"));
+            }
+        }
+
+        None
+    }
+}
+
+pub enum RegionArea {
+    Box(LocationBox),
+    Route(Route),
+}
+
+impl RegionArea {
+    pub fn location_boxes(&self) -> Vec<LocationBox> {
+        match self {
+            RegionArea::Box(location_box) => vec![location_box.clone()],
+            RegionArea::Route(route) => route.location_boxes(),
+        }
+    }
+
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        match self {
+            RegionArea::Box(_) => true,
+            RegionArea::Route(route) => route.contains(lat, lng),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RegionConfiguration {
+    name: String,
+    first: Option<Coordinate>,
+    second: Option<Coordinate>,
+    route: Option<RouteConfiguration>,
+    types: Option<Vec<LocationType>>,
+    categories: Option<Vec<Category>>,
+    filter: Option<PoiFilter>,
+    chat_id: Option<String>,
+    notifiers: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct RouteConfiguration {
+    gpx_file: Option<String>,
+    polyline: Option<String>,
+    polyline_file: Option<String>,
+    buffer: f64,
+    max_box_size: Option<f64>,
+}
+
+fn region_area(region: &RegionConfiguration) -> RegionArea {
+    match (&region.first, &region.second, &region.route) {
+        (Some(first), Some(second), None) => RegionArea::Box(LocationBox {
+            lat_min: first.lat.min(second.lat),
+            lng_min: first.lng.min(second.lng),
+            lat_max: first.lat.max(second.lat),
+            lng_max: first.lng.max(second.lng),
+        }),
+        (None, None, Some(route)) => RegionArea::Route(
+            Route::try_new(route_lines(&region.name, route), route.buffer, route.max_box_size.unwrap_or(25.0) * 1000.0)
+                .unwrap_or_else(|error| panic!("Route of region {} should be valid: {error}", region.name)),
+        ),
+        _ => panic!(
+            "Configuration of region {} should have either a first and second corner or a route",
+            region.name
+        ),
+    }
+}
+
+fn route_lines(region_name: &str, route: &RouteConfiguration) -> Vec<Vec<Coordinate>> {
+    let read_file = |path: &String| {
+        std::fs::read_to_string(path)
+            .unwrap_or_else(|error| panic!("Route file {path} of region {region_name} should be readable: {error}"))
+    };
+
+    let lines = match (&route.gpx_file, &route.polyline, &route.polyline_file) {
+        (Some(gpx_file), None, None) => Route::parse_gpx(&read_file(gpx_file)),
+        (None, Some(polyline), None) => Route::parse_polyline(polyline).map(|points| vec![points]),
+        (None, None, Some(polyline_file)) => Route::parse_polyline(&read_file(polyline_file)).map(|points| vec![points]),
+        _ => panic!("Route of region {region_name} should have exactly one of gpx_file, polyline or polyline_file"),
+    };
+
+    lines.unwrap_or_else(|error| panic!("Route of region {region_name} should be valid: {error}"))
+}
+
+async fn get_coordinate(key: &str) -> Coordinate {
+    Coordinate {
+        lat: get_float(&format!("{key}.lat")).await,
+        lng: get_float(&format!("{key}.lng")).await,
+    }
+}
+
 async fn get_float(key: &str) -> f64 {
     let config = config().read().await;
 
@@ -76,3 +562,137 @@ async fn get_int(key: &str) -> i64 {
         .get_int(key)
         .unwrap_or_else(|_| panic!("Configuration should have a int on {key}"))
 }
+
+async fn get_optional_int(key: &str) -> Option<i64> {
+    let config = config().read().await;
+
+    match config.get_int(key) {
+        Ok(value) => Some(value),
+        Err(config::ConfigError::NotFound(_)) => None,
+        Err(error) => panic!("Configuration should have a int on {key}: {error}"),
+    }
+}
+
+async fn get_optional_string(key: &str) -> Option<String> {
+    let config = config().read().await;
+
+    match config.get_string(key) {
+        Ok(value) => Some(value),
+        Err(config::ConfigError::NotFound(_)) => None,
+        Err(error) => panic!("Configuration should have a string on {key}: {error}"),
+    }
+}
+
+async fn get_optional_float(key: &str) -> Option<f64> {
+    let config = config().read().await;
+
+    match config.get_float(key) {
+        Ok(value) => Some(value),
+        Err(config::ConfigError::NotFound(_)) => None,
+        Err(error) => panic!("Configuration should have a float on {key}: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn poi(poi_type: &str, vmax: Option<i32>, confirm_age_days: Option<i64>) -> DetailedPoi {
+        let mut poi: DetailedPoi = serde_json::from_value(json!({
+            "id": "1",
+            "lat": 53.55,
+            "lng": 9.99,
+            "address": {"country": "DE", "state": "HH", "zip_code": "20095", "city": "Hamburg", "city_district": "", "street": "Street"},
+            "content": "",
+            "backend": "b-1",
+            "type": poi_type,
+            "vmax": vmax.unwrap_or(0),
+            "create_date": "01.01.1970",
+            "confirm_date": "01.01.1970",
+            "info": {"desc": null},
+        }))
+        .unwrap();
+        poi.confirm_date = confirm_age_days.map(|days| Local::now().naive_local() - Duration::days(days));
+        poi
+    }
+
+    #[test]
+    fn filter_accepts_by_default() {
+        assert_eq!(PoiFilter::default().rejects(&poi("103", Some(50), Some(1000))), None);
+    }
+
+    #[test]
+    fn filter_rejects_ignored_backend_ids() {
+        let filter = PoiFilter { ignored_backend_ids: vec![String::from("b-1")], ..PoiFilter::default() };
+
+        assert!(filter.rejects(&poi("103", Some(50), None)).is_some());
+    }
+
+    #[test]
+    fn filter_rejects_categories() {
+        let include = PoiFilter { include_categories: Some(vec![Category::Fixed]), ..PoiFilter::default() };
+        assert_eq!(include.rejects(&poi("101", None, None)), None);
+        assert!(include.rejects(&poi("0", None, None)).is_some());
+        assert!(include.rejects(&poi("999", None, None)).is_some());
+
+        let exclude = PoiFilter { exclude_categories: vec![Category::Mobile], ..PoiFilter::default() };
+        assert_eq!(exclude.rejects(&poi("101", None, None)), None);
+        assert!(exclude.rejects(&poi("0", None, None)).is_some());
+    }
+
+    #[test]
+    fn filter_rejects_speed_limits_which_are_not_below() {
+        let filter = PoiFilter { vmax_below: Some(60), ..PoiFilter::default() };
+
+        assert_eq!(filter.rejects(&poi("103", Some(50), None)), None);
+        assert!(filter.rejects(&poi("103", Some(60), None)).is_some());
+        assert_eq!(filter.rejects(&poi("103", None, None)), None);
+    }
+
+    #[test]
+    fn filter_rejects_old_confirmations() {
+        let filter = PoiFilter { max_confirm_age_days: Some(7), ..PoiFilter::default() };
+
+        assert_eq!(filter.rejects(&poi("103", None, Some(6))), None);
+        assert!(filter.rejects(&poi("103", None, Some(8))).is_some());
+        assert_eq!(filter.rejects(&poi("103", None, None)), None);
+
+        let mut unconfirmed = poi("103", None, None);
+        unconfirmed.create_date = Some(Local::now().naive_local() - Duration::days(8));
+        assert!(filter.rejects(&unconfirmed).is_some());
+    }
+
+    #[test]
+    fn is_gone_after_the_first_missed_run_by_default() {
+        let grace = GraceConfiguration { missed_runs: None, missed_minutes: None };
+
+        assert!(!grace.is_gone(0, Duration::zero()));
+        assert!(grace.is_gone(1, Duration::zero()));
+    }
+
+    #[test]
+    fn is_gone_after_missed_runs() {
+        let grace = GraceConfiguration { missed_runs: Some(3), missed_minutes: None };
+
+        assert!(!grace.is_gone(2, Duration::days(7)));
+        assert!(grace.is_gone(3, Duration::zero()));
+    }
+
+    #[test]
+    fn is_gone_after_missed_minutes() {
+        let grace = GraceConfiguration { missed_runs: None, missed_minutes: Some(60) };
+
+        assert!(!grace.is_gone(100, Duration::minutes(59)));
+        assert!(grace.is_gone(1, Duration::minutes(60)));
+    }
+
+    #[test]
+    fn is_gone_after_missed_runs_or_minutes() {
+        let grace = GraceConfiguration { missed_runs: Some(3), missed_minutes: Some(60) };
+
+        assert!(!grace.is_gone(2, Duration::minutes(59)));
+        assert!(grace.is_gone(3, Duration::minutes(1)));
+        assert!(grace.is_gone(1, Duration::minutes(60)));
+    }
+}
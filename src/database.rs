@@ -1,122 +1,350 @@
 use crate::configuration;
-use crate::model::DetailedPoi;
-use mysql::prelude::Queryable;
-use mysql::{params, Pool, PooledConn};
-use teloxide::types::{ChatId, MessageId};
-
-pub struct Repository {
-    connection: PooledConn,
-}
-impl Repository {
-    pub async fn try_new() -> anyhow::Result<Self> {
-        let connection_uri = configuration::get_mysql_connection_uri().await;
-        let pool = Pool::new(connection_uri.as_str())?;
-
-        let mut conn = pool.get_conn()?;
-
-        conn.query_drop(
-            "CREATE TABLE IF NOT EXISTS known_blitzer (
-                id VARCHAR(255) PRIMARY KEY,
-                lat VARCHAR(255) NOT NULL,
-                lng VARCHAR(255) NOT NULL,
-                address_country VARCHAR(255) NOT NULL,
-                address_state VARCHAR(255) NOT NULL,
-                address_zip_code VARCHAR(255) NOT NULL,
-                address_city VARCHAR(255) NOT NULL,
-                address_city_district VARCHAR(255) NOT NULL,
-                address_street VARCHAR(255) NOT NULL,
-                content TEXT NOT NULL,
-                backend VARCHAR(255) NOT NULL,
-                poi_type VARCHAR(255) NOT NULL,
-                vmax VARCHAR(255) NOT NULL,
-                create_date VARCHAR(255) NOT NULL,
-                confirm_date VARCHAR(255) NOT NULL,
-                info_desc TEXT,
-                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
-                last_seen DATETIME DEFAULT NULL,
-                chat_id BIGINT NOT NULL,
-                message_id_info INT NOT NULL,
-                message_id_location INT NOT NULL
-            )",
-        )?;
-
-        Ok(Self { connection: conn })
+use crate::configuration::DatabaseConfiguration;
+use crate::database::migrations::{Dialect, Migration};
+use crate::database::mysql_repository::MysqlRepository;
+use crate::database::sqlite_repository::SqliteRepository;
+use crate::model::{Address, DetailedPoi};
+use chrono::{NaiveDateTime, NaiveTime};
+use std::fmt::Display;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+pub mod migrations;
+mod mysql_repository;
+mod sqlite_repository;
+
+/// A failed query of the storage. Writes of several rows are done in a transaction, so a failed write
+/// leaves the database unchanged.
+#[derive(Debug)]
+pub enum StorageError {
+    Mysql(mysql::Error),
+    Sqlite(rusqlite::Error),
+    // A stored value which could not be read, e.g. a state written by a newer version
+    Decode { column: String, error: String },
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Mysql(error) => write!(f, "Mysql query failed: {}", error),
+            StorageError::Sqlite(error) => write!(f, "SQLite query failed: {}", error),
+            StorageError::Decode { column, error } => write!(f, "Invalid value in column {}: {}", column, error),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Mysql(error) => Some(error),
+            StorageError::Sqlite(error) => Some(error),
+            StorageError::Decode { .. } => None,
+        }
     }
+}
 
-    pub fn add_poi(
-        &mut self,
-        poi: DetailedPoi,
-        chat_id: ChatId,
-        info_message_id: MessageId,
-        location_message_id: MessageId,
-    ) {
-        self.connection.exec_drop(
-            r"INSERT INTO known_blitzer (
-                    id, lat, lng, address_country, address_state, address_zip_code, address_city,
-                    address_city_district, address_street, content, backend, poi_type, vmax,
-                    create_date, confirm_date, info_desc, chat_id, message_id_info, message_id_location
-                ) VALUES (
-                    :id, :lat, :lng, :address_country, :address_state, :address_zip_code, :address_city,
-                    :address_city_district, :address_street, :content, :backend, :poi_type, :vmax,
-                    :create_date, :confirm_date, :info_desc, :chat_id, :message_id_info, :message_id_location
-                )",
-            params! {
-                "id" => poi.id,
-                "lat" => poi.lat,
-                "lng" => poi.lng,
-                "address_country" => poi.address.country,
-                "address_state" => poi.address.state,
-                "address_zip_code" => poi.address.zip_code,
-                "address_city" => poi.address.city,
-                "address_city_district" => poi.address.city_district,
-                "address_street" => poi.address.street,
-                "content" => poi.content,
-                "backend" => poi.backend,
-                "poi_type" => poi.poi_type,
-                "vmax" => poi.vmax,
-                "create_date" => poi.create_date,
-                "confirm_date" => poi.confirm_date,
-                "info_desc" => poi.info.desc.as_deref(),
-                "chat_id" => chat_id.0,
-                "message_id_info" => info_message_id.0,
-                "message_id_location" => location_message_id.0,
-            }
-        ).expect("Should write poi to database");
+impl From<mysql::Error> for StorageError {
+    fn from(error: mysql::Error) -> Self {
+        StorageError::Mysql(error)
     }
+}
 
-    pub fn get_known_pois(&mut self) -> Vec<KnownPoi> {
-        let known_blitzer: Vec<KnownPoi> = self
-            .connection
-            .query_map(
-                "SELECT id,backend,chat_id,message_id_info,message_id_location from known_blitzer WHERE last_seen IS NULL",
-                |(id, backend_id, chat_id, message_id_info, message_id_location)| KnownPoi {
-                    id,
-                    backend_id,
-                    chat_id,
-                    message_id_info,
-                    message_id_location,
-                },
-            )
-            .expect("Should get backend id of poi from database");
-
-        known_blitzer
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error {
+            // Decode errors raised while mapping a row are passed through by rusqlite as conversion failure
+            rusqlite::Error::FromSqlConversionFailure(index, value_type, error) => match error.downcast::<StorageError>() {
+                Ok(error) => *error,
+                Err(error) => StorageError::Sqlite(rusqlite::Error::FromSqlConversionFailure(index, value_type, error)),
+            },
+            error => StorageError::Sqlite(error),
+        }
     }
+}
+
+/// Parses a text column into one of the stored enums.
+fn parse_column<T: FromStr>(column: &str, value: String) -> StorageResult<T> {
+    value.parse().map_err(|_| StorageError::Decode {
+        column: column.to_owned(),
+        error: format!("unknown value {}", value),
+    })
+}
+
+pub trait Storage: Send {
+    fn dialect(&self) -> Dialect;
+
+    fn schema_version(&mut self) -> anyhow::Result<u32>;
+
+    fn apply_migration(&mut self, migration: &Migration) -> anyhow::Result<()>;
+
+    fn add_poi(
+        &mut self,
+        region: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()>;
+
+    fn get_known_pois(&mut self, region: &str) -> StorageResult<Vec<KnownPoi>>;
+
+    fn find_inactive_poi(&mut self, region: &str, poi_id: &str, backend_id: &str) -> StorageResult<Option<KnownPoi>>;
+
+    fn reactivate_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()>;
+
+    fn update_last_seen(&mut self, region: &str, poi_id: String) -> StorageResult<()>;
+
+    fn update_missed(&mut self, region: &str, poi_id: &str, missed_runs: u32, first_missed_at: Option<NaiveDateTime>) -> StorageResult<()>;
+
+    fn update_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: &DetailedPoi,
+        message_ids: Option<&MessageIds>,
+        changes: &[PoiChange],
+    ) -> StorageResult<()>;
+
+    fn add_event(&mut self, region: &str, poi_id: &str, event_type: PoiEventType, details: Option<String>) -> StorageResult<()>;
+
+    fn get_timeline(&mut self, backend_id: &str) -> StorageResult<Vec<PoiEvent>>;
+
+    fn get_active_pois(&mut self) -> StorageResult<Vec<ActivePoi>>;
+
+    fn set_muted_until(&mut self, chat_id: &str, muted_until: Option<NaiveDateTime>) -> StorageResult<()>;
+
+    fn get_muted_until(&mut self, chat_id: &str) -> StorageResult<Option<NaiveDateTime>>;
+
+    fn add_dead_letter(&mut self, notifier: &str, url: &str, payload: &str, error: &str) -> StorageResult<()>;
+
+    /// Records a type code sent by the api. Returns true if the code was never seen before.
+    fn record_poi_type(&mut self, code: &str, backend_id: &str) -> StorageResult<bool>;
+
+    fn get_poi_types(&mut self) -> StorageResult<Vec<PoiType>>;
+
+    fn get_deliveries(&mut self, region: &str) -> StorageResult<Vec<Delivery>>;
+
+    /// Adds a pending delivery and returns its id.
+    fn add_delivery(
+        &mut self,
+        region: &str,
+        backend_id: &str,
+        event: DeliveryEvent,
+        notifier: &str,
+        details: &str,
+    ) -> StorageResult<i64>;
+
+    fn update_delivery(&mut self, id: i64, state: DeliveryState, message_ids: Option<&MessageIds>) -> StorageResult<()>;
+
+    /// Marks the sent deliveries of a poi as persisted, once the poi itself was written.
+    fn persist_deliveries(&mut self, region: &str, backend_id: &str) -> StorageResult<()>;
+
+    fn delete_delivery(&mut self, id: i64) -> StorageResult<()>;
+}
 
-    pub fn update_last_seen(&mut self, poi_id: String) {
-        self.connection.exec_drop(
-            r"UPDATE known_blitzer SET last_seen = CURRENT_TIMESTAMP() WHERE id = :id",
-            params! {
-                "id" => poi_id,
-            }
-        ).expect("Should write poi to database");
+pub async fn try_new() -> anyhow::Result<Box<dyn Storage>> {
+    let mut storage = open().await?;
+    migrations::run(storage.as_mut())?;
 
+    Ok(storage)
+}
+
+/// Opens the configured storage without touching the schema.
+pub async fn open() -> anyhow::Result<Box<dyn Storage>> {
+    match configuration::get_database_configuration().await {
+        DatabaseConfiguration::Mysql { connection_uri } => {
+            Ok(Box::new(MysqlRepository::try_new(&connection_uri)?))
+        }
+        DatabaseConfiguration::Sqlite { path } => Ok(Box::new(SqliteRepository::try_new(&path)?)),
     }
 }
 
 pub struct KnownPoi {
     pub id: String,
     pub backend_id: String,
+    pub message_ids: Option<MessageIds>,
+    pub address: Address,
+    pub poi_type: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub vmax: Option<i32>,
+    pub create_date: Option<NaiveDateTime>,
+    pub confirm_date: Option<NaiveDateTime>,
+    pub info_desc: Option<String>,
+    pub last_seen: Option<NaiveDateTime>,
+    pub missed_runs: u32,
+    pub first_missed_at: Option<NaiveDateTime>,
+}
+
+impl KnownPoi {
+    pub fn changes(&self, poi: &DetailedPoi) -> Vec<PoiChange> {
+        let mut changes = Vec::new();
+
+        if self.vmax != poi.vmax {
+            changes.push(PoiChange::new("vmax", self.vmax, poi.vmax));
+        }
+        if !is_same_api_date(self.confirm_date, poi.confirm_date) {
+            changes.push(PoiChange::new("confirm_date", self.confirm_date, poi.confirm_date));
+        }
+        if self.info_desc != poi.info.desc {
+            changes.push(PoiChange::new("info_desc", self.info_desc.clone(), poi.info.desc.clone()));
+        }
+        // The coordinates are stored with 7 decimals, so smaller differences are no movement
+        if (self.lat - poi.lat).abs() > 1e-6 || (self.lng - poi.lng).abs() > 1e-6 {
+            changes.push(PoiChange::new(
+                "location",
+                Some(format!("{},{}", self.lat, self.lng)),
+                Some(format!("{},{}", poi.lat, poi.lng)),
+            ));
+        }
+
+        changes
+    }
+}
+
+/// Telegram messages of a poi, which are edited and deleted later on. Pois which were not
+/// announced in telegram have none, single formatted messages have no separate location.
+#[derive(Clone, Debug)]
+pub struct MessageIds {
     pub chat_id: i64,
-    pub message_id_info: i32,
-    pub message_id_location: i32,
+    pub info: i32,
+    pub location: Option<i32>,
+}
+
+impl MessageIds {
+    pub fn from_columns(chat_id: Option<i64>, info: Option<i32>, location: Option<i32>) -> Option<MessageIds> {
+        Some(MessageIds {
+            chat_id: chat_id?,
+            info: info?,
+            location,
+        })
+    }
+}
+
+// The api only sends the time for today and the date without time for older dates,
+// so a time of today becomes a date without time on the next day
+fn is_same_api_date(first: Option<NaiveDateTime>, second: Option<NaiveDateTime>) -> bool {
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            first == second
+                || (first.date() == second.date() && (first.time() == NaiveTime::MIN || second.time() == NaiveTime::MIN))
+        }
+        (first, second) => first == second,
+    }
+}
+
+pub struct PoiChange {
+    pub field: &'static str,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+impl PoiChange {
+    fn new<T: ToString>(field: &'static str, old_value: Option<T>, new_value: Option<T>) -> PoiChange {
+        PoiChange {
+            field,
+            old_value: old_value.map(|value| value.to_string()),
+            new_value: new_value.map(|value| value.to_string()),
+        }
+    }
+
+    pub fn is_movement(&self) -> bool {
+        self.field == "location"
+    }
+
+    pub fn event_type(&self) -> Option<PoiEventType> {
+        match self.field {
+            "vmax" => Some(PoiEventType::SpeedChanged),
+            "confirm_date" => Some(PoiEventType::Confirmed),
+            "location" => Some(PoiEventType::Moved),
+            _ => None,
+        }
+    }
+}
+
+impl Display for PoiChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            self.old_value.as_deref().unwrap_or("-"),
+            self.new_value.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum PoiEventType {
+    Appeared,
+    Confirmed,
+    SpeedChanged,
+    Moved,
+    Disappeared,
+    Reappeared,
+}
+
+pub struct PoiEvent {
+    pub region: String,
+    pub poi_id: String,
+    pub event_type: PoiEventType,
+    pub details: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+pub struct ActivePoi {
+    pub region: String,
+    pub backend_id: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub vmax: Option<i32>,
+    pub poi_type: String,
+    pub address_zip_code: String,
+    pub address_city: String,
+    pub address_street: String,
+    pub first_seen: NaiveDateTime,
+}
+
+/// A type code sent by the api, with the last poi that used it.
+pub struct PoiType {
+    pub code: String,
+    pub backend_id: String,
+    pub first_seen: NaiveDateTime,
+    pub last_seen: NaiveDateTime,
+}
+
+/// A notification of a poi in the outbox. It is pending while it is sent, sent once the notifier accepted it and
+/// persisted once the poi was written as well.
+pub struct Delivery {
+    pub id: i64,
+    pub backend_id: String,
+    pub event: DeliveryEvent,
+    pub notifier: String,
+    pub details: String,
+    pub state: DeliveryState,
+    pub message_ids: Option<MessageIds>,
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "snake_case")]
+pub enum DeliveryEvent {
+    Appeared,
+    Reappeared,
+    Changed,
+    Disappeared,
+}
+
+#[derive(Display, EnumString, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum DeliveryState {
+    Pending,
+    Sent,
+    Persisted,
 }
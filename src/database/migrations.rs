@@ -0,0 +1,439 @@
+use crate::database::Storage;
+
+pub enum Dialect {
+    Mysql,
+    Sqlite,
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    mysql: &'static [&'static str],
+    sqlite: &'static [&'static str],
+}
+
+impl Migration {
+    pub fn statements(&self, dialect: &Dialect) -> &'static [&'static str] {
+        match dialect {
+            Dialect::Mysql => self.mysql,
+            Dialect::Sqlite => self.sqlite,
+        }
+    }
+}
+
+// Migrations are applied in this order and must never be changed once released. Add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create known_blitzer",
+        mysql: &["CREATE TABLE IF NOT EXISTS known_blitzer (
+                id VARCHAR(255) PRIMARY KEY,
+                lat VARCHAR(255) NOT NULL,
+                lng VARCHAR(255) NOT NULL,
+                address_country VARCHAR(255) NOT NULL,
+                address_state VARCHAR(255) NOT NULL,
+                address_zip_code VARCHAR(255) NOT NULL,
+                address_city VARCHAR(255) NOT NULL,
+                address_city_district VARCHAR(255) NOT NULL,
+                address_street VARCHAR(255) NOT NULL,
+                content TEXT NOT NULL,
+                backend VARCHAR(255) NOT NULL,
+                poi_type VARCHAR(255) NOT NULL,
+                vmax VARCHAR(255) NOT NULL,
+                create_date VARCHAR(255) NOT NULL,
+                confirm_date VARCHAR(255) NOT NULL,
+                info_desc TEXT,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT NULL,
+                chat_id BIGINT NOT NULL,
+                message_id_info INT NOT NULL,
+                message_id_location INT NOT NULL
+            )"],
+        sqlite: &["CREATE TABLE IF NOT EXISTS known_blitzer (
+                id TEXT PRIMARY KEY,
+                lat TEXT NOT NULL,
+                lng TEXT NOT NULL,
+                address_country TEXT NOT NULL,
+                address_state TEXT NOT NULL,
+                address_zip_code TEXT NOT NULL,
+                address_city TEXT NOT NULL,
+                address_city_district TEXT NOT NULL,
+                address_street TEXT NOT NULL,
+                content TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                poi_type TEXT NOT NULL,
+                vmax TEXT NOT NULL,
+                create_date TEXT NOT NULL,
+                confirm_date TEXT NOT NULL,
+                info_desc TEXT,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT NULL,
+                chat_id INTEGER NOT NULL,
+                message_id_info INTEGER NOT NULL,
+                message_id_location INTEGER NOT NULL
+            )"],
+    },
+    Migration {
+        version: 2,
+        description: "Track known pois per region",
+        mysql: &[
+            "ALTER TABLE known_blitzer ADD COLUMN region VARCHAR(255) NOT NULL DEFAULT 'default' AFTER id",
+            "ALTER TABLE known_blitzer DROP PRIMARY KEY, ADD PRIMARY KEY (id, region)",
+        ],
+        // SQLite can't change a primary key, so the table is copied
+        sqlite: &[
+            "CREATE TABLE known_blitzer_new (
+                id TEXT NOT NULL,
+                region TEXT NOT NULL DEFAULT 'default',
+                lat TEXT NOT NULL,
+                lng TEXT NOT NULL,
+                address_country TEXT NOT NULL,
+                address_state TEXT NOT NULL,
+                address_zip_code TEXT NOT NULL,
+                address_city TEXT NOT NULL,
+                address_city_district TEXT NOT NULL,
+                address_street TEXT NOT NULL,
+                content TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                poi_type TEXT NOT NULL,
+                vmax TEXT NOT NULL,
+                create_date TEXT NOT NULL,
+                confirm_date TEXT NOT NULL,
+                info_desc TEXT,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT NULL,
+                chat_id INTEGER NOT NULL,
+                message_id_info INTEGER NOT NULL,
+                message_id_location INTEGER NOT NULL,
+                PRIMARY KEY (id, region)
+            )",
+            "INSERT INTO known_blitzer_new (
+                id, lat, lng, address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type, vmax,
+                create_date, confirm_date, info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location
+            ) SELECT
+                id, lat, lng, address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type, vmax,
+                create_date, confirm_date, info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location
+            FROM known_blitzer",
+            "DROP TABLE known_blitzer",
+            "ALTER TABLE known_blitzer_new RENAME TO known_blitzer",
+        ],
+    },
+    Migration {
+        version: 3,
+        description: "Store coordinates, speed limit and dates with proper types",
+        mysql: &[
+            "ALTER TABLE known_blitzer
+                ADD COLUMN vmax_new INT NULL AFTER vmax,
+                ADD COLUMN create_date_new DATETIME NULL AFTER create_date,
+                ADD COLUMN confirm_date_new DATETIME NULL AFTER confirm_date",
+            "UPDATE known_blitzer SET vmax_new = CAST(vmax AS SIGNED) WHERE vmax REGEXP '^[0-9]+$' AND vmax > 0",
+            "UPDATE known_blitzer SET create_date_new = CASE
+                WHEN create_date = '01.01.1970' THEN NULL
+                WHEN create_date REGEXP '^[0-9]{2}[.][0-9]{2}[.][0-9]{4}$' THEN STR_TO_DATE(create_date, '%d.%m.%Y')
+                WHEN create_date REGEXP '^[0-9]{2}[.][0-9]{2}[.][0-9]{4} [0-9]{2}:[0-9]{2}$'
+                    THEN STR_TO_DATE(create_date, '%d.%m.%Y %H:%i')
+                WHEN create_date REGEXP '^[0-9]{2}:[0-9]{2}$' THEN TIMESTAMP(DATE(first_seen), STR_TO_DATE(create_date, '%H:%i'))
+                ELSE NULL
+            END",
+            "UPDATE known_blitzer SET confirm_date_new = CASE
+                WHEN confirm_date = '01.01.1970' THEN NULL
+                WHEN confirm_date REGEXP '^[0-9]{2}[.][0-9]{2}[.][0-9]{4}$' THEN STR_TO_DATE(confirm_date, '%d.%m.%Y')
+                WHEN confirm_date REGEXP '^[0-9]{2}[.][0-9]{2}[.][0-9]{4} [0-9]{2}:[0-9]{2}$'
+                    THEN STR_TO_DATE(confirm_date, '%d.%m.%Y %H:%i')
+                WHEN confirm_date REGEXP '^[0-9]{2}:[0-9]{2}$' THEN TIMESTAMP(DATE(first_seen), STR_TO_DATE(confirm_date, '%H:%i'))
+                ELSE NULL
+            END",
+            "ALTER TABLE known_blitzer
+                DROP COLUMN vmax,
+                DROP COLUMN create_date,
+                DROP COLUMN confirm_date,
+                CHANGE COLUMN vmax_new vmax INT NULL,
+                CHANGE COLUMN create_date_new create_date DATETIME NULL,
+                CHANGE COLUMN confirm_date_new confirm_date DATETIME NULL,
+                MODIFY COLUMN lat DECIMAL(10, 7) NOT NULL,
+                MODIFY COLUMN lng DECIMAL(10, 7) NOT NULL",
+        ],
+        sqlite: &[
+            "CREATE TABLE known_blitzer_new (
+                id TEXT NOT NULL,
+                region TEXT NOT NULL DEFAULT 'default',
+                lat REAL NOT NULL,
+                lng REAL NOT NULL,
+                address_country TEXT NOT NULL,
+                address_state TEXT NOT NULL,
+                address_zip_code TEXT NOT NULL,
+                address_city TEXT NOT NULL,
+                address_city_district TEXT NOT NULL,
+                address_street TEXT NOT NULL,
+                content TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                poi_type TEXT NOT NULL,
+                vmax INTEGER,
+                create_date DATETIME,
+                confirm_date DATETIME,
+                info_desc TEXT,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT NULL,
+                chat_id INTEGER NOT NULL,
+                message_id_info INTEGER NOT NULL,
+                message_id_location INTEGER NOT NULL,
+                PRIMARY KEY (id, region)
+            )",
+            "INSERT INTO known_blitzer_new (
+                id, region, lat, lng, address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type, vmax,
+                create_date, confirm_date, info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location
+            ) SELECT
+                id, region, CAST(lat AS REAL), CAST(lng AS REAL), address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type,
+                CASE WHEN CAST(vmax AS INTEGER) > 0 THEN CAST(vmax AS INTEGER) END,
+                CASE
+                    WHEN create_date = '01.01.1970' THEN NULL
+                    WHEN create_date GLOB '[0-9][0-9].[0-9][0-9].[0-9][0-9][0-9][0-9]'
+                        THEN substr(create_date, 7, 4) || '-' || substr(create_date, 4, 2) || '-' || substr(create_date, 1, 2) || ' 00:00:00'
+                    WHEN create_date GLOB '[0-9][0-9].[0-9][0-9].[0-9][0-9][0-9][0-9] [0-9][0-9]:[0-9][0-9]'
+                        THEN substr(create_date, 7, 4) || '-' || substr(create_date, 4, 2) || '-' || substr(create_date, 1, 2) || ' ' || substr(create_date, 12, 5) || ':00'
+                    WHEN create_date GLOB '[0-9][0-9]:[0-9][0-9]' THEN date(first_seen) || ' ' || create_date || ':00'
+                    ELSE NULL
+                END,
+                CASE
+                    WHEN confirm_date = '01.01.1970' THEN NULL
+                    WHEN confirm_date GLOB '[0-9][0-9].[0-9][0-9].[0-9][0-9][0-9][0-9]'
+                        THEN substr(confirm_date, 7, 4) || '-' || substr(confirm_date, 4, 2) || '-' || substr(confirm_date, 1, 2) || ' 00:00:00'
+                    WHEN confirm_date GLOB '[0-9][0-9].[0-9][0-9].[0-9][0-9][0-9][0-9] [0-9][0-9]:[0-9][0-9]'
+                        THEN substr(confirm_date, 7, 4) || '-' || substr(confirm_date, 4, 2) || '-' || substr(confirm_date, 1, 2) || ' ' || substr(confirm_date, 12, 5) || ':00'
+                    WHEN confirm_date GLOB '[0-9][0-9]:[0-9][0-9]' THEN date(first_seen) || ' ' || confirm_date || ':00'
+                    ELSE NULL
+                END,
+                info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location
+            FROM known_blitzer",
+            "DROP TABLE known_blitzer",
+            "ALTER TABLE known_blitzer_new RENAME TO known_blitzer",
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "Create poi_changes",
+        mysql: &["CREATE TABLE poi_changes (
+                id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                poi_id VARCHAR(255) NOT NULL,
+                region VARCHAR(255) NOT NULL,
+                field VARCHAR(255) NOT NULL,
+                old_value TEXT,
+                new_value TEXT,
+                changed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                INDEX poi_changes_poi (poi_id, region)
+            )"],
+        sqlite: &[
+            "CREATE TABLE poi_changes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                poi_id TEXT NOT NULL,
+                region TEXT NOT NULL,
+                field TEXT NOT NULL,
+                old_value TEXT,
+                new_value TEXT,
+                changed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            "CREATE INDEX poi_changes_poi ON poi_changes (poi_id, region)",
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "Create poi_events",
+        mysql: &["CREATE TABLE poi_events (
+                id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                poi_id VARCHAR(255) NOT NULL,
+                region VARCHAR(255) NOT NULL,
+                event_type VARCHAR(64) NOT NULL,
+                details TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                INDEX poi_events_poi (poi_id, region)
+            )"],
+        sqlite: &[
+            "CREATE TABLE poi_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                poi_id TEXT NOT NULL,
+                region TEXT NOT NULL,
+                event_type TEXT NOT NULL,
+                details TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            "CREATE INDEX poi_events_poi ON poi_events (poi_id, region)",
+        ],
+    },
+    Migration {
+        version: 6,
+        description: "Track missed runs of known pois",
+        mysql: &[
+            "ALTER TABLE known_blitzer
+                ADD COLUMN missed_runs INT NOT NULL DEFAULT 0,
+                ADD COLUMN first_missed_at DATETIME NULL",
+        ],
+        sqlite: &[
+            "ALTER TABLE known_blitzer ADD COLUMN missed_runs INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE known_blitzer ADD COLUMN first_missed_at DATETIME NULL",
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "Create chat_mutes",
+        mysql: &["CREATE TABLE chat_mutes (
+                chat_id VARCHAR(255) PRIMARY KEY,
+                muted_until DATETIME NOT NULL
+            )"],
+        sqlite: &["CREATE TABLE chat_mutes (
+                chat_id TEXT PRIMARY KEY,
+                muted_until DATETIME NOT NULL
+            )"],
+    },
+    Migration {
+        version: 8,
+        description: "Allow pois without telegram messages",
+        mysql: &[
+            "ALTER TABLE known_blitzer
+                MODIFY COLUMN chat_id BIGINT NULL,
+                MODIFY COLUMN message_id_info INT NULL,
+                MODIFY COLUMN message_id_location INT NULL",
+        ],
+        sqlite: &[
+            "CREATE TABLE known_blitzer_new (
+                id TEXT NOT NULL,
+                region TEXT NOT NULL DEFAULT 'default',
+                lat REAL NOT NULL,
+                lng REAL NOT NULL,
+                address_country TEXT NOT NULL,
+                address_state TEXT NOT NULL,
+                address_zip_code TEXT NOT NULL,
+                address_city TEXT NOT NULL,
+                address_city_district TEXT NOT NULL,
+                address_street TEXT NOT NULL,
+                content TEXT NOT NULL,
+                backend TEXT NOT NULL,
+                poi_type TEXT NOT NULL,
+                vmax INTEGER,
+                create_date DATETIME,
+                confirm_date DATETIME,
+                info_desc TEXT,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT NULL,
+                chat_id INTEGER,
+                message_id_info INTEGER,
+                message_id_location INTEGER,
+                missed_runs INTEGER NOT NULL DEFAULT 0,
+                first_missed_at DATETIME NULL,
+                PRIMARY KEY (id, region)
+            )",
+            "INSERT INTO known_blitzer_new (
+                id, region, lat, lng, address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type, vmax,
+                create_date, confirm_date, info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location,
+                missed_runs, first_missed_at
+            ) SELECT
+                id, region, lat, lng, address_country, address_state, address_zip_code, address_city,
+                address_city_district, address_street, content, backend, poi_type, vmax,
+                create_date, confirm_date, info_desc, first_seen, last_seen, chat_id, message_id_info, message_id_location,
+                missed_runs, first_missed_at
+            FROM known_blitzer",
+            "DROP TABLE known_blitzer",
+            "ALTER TABLE known_blitzer_new RENAME TO known_blitzer",
+        ],
+    },
+    Migration {
+        version: 9,
+        description: "Create webhook_dead_letters",
+        mysql: &["CREATE TABLE webhook_dead_letters (
+                id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                notifier VARCHAR(255) NOT NULL,
+                url TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                error TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )"],
+        sqlite: &["CREATE TABLE webhook_dead_letters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                notifier TEXT NOT NULL,
+                url TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                error TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )"],
+    },
+    Migration {
+        version: 10,
+        description: "Create poi_types",
+        mysql: &["CREATE TABLE poi_types (
+                code VARCHAR(32) PRIMARY KEY,
+                backend_id VARCHAR(255) NOT NULL,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT CURRENT_TIMESTAMP
+            )"],
+        sqlite: &["CREATE TABLE poi_types (
+                code TEXT PRIMARY KEY,
+                backend_id TEXT NOT NULL,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_seen DATETIME DEFAULT CURRENT_TIMESTAMP
+            )"],
+    },
+    Migration {
+        version: 11,
+        description: "Create notification_outbox",
+        mysql: &["CREATE TABLE notification_outbox (
+                id BIGINT AUTO_INCREMENT PRIMARY KEY,
+                region VARCHAR(255) NOT NULL,
+                backend_id VARCHAR(255) NOT NULL,
+                event VARCHAR(32) NOT NULL,
+                notifier VARCHAR(255) NOT NULL,
+                details TEXT NOT NULL,
+                state VARCHAR(32) NOT NULL,
+                chat_id BIGINT NULL,
+                message_id_info INT NULL,
+                message_id_location INT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                INDEX notification_outbox_region (region)
+            )"],
+        sqlite: &[
+            "CREATE TABLE notification_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                region TEXT NOT NULL,
+                backend_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                notifier TEXT NOT NULL,
+                details TEXT NOT NULL,
+                state TEXT NOT NULL,
+                chat_id INTEGER NULL,
+                message_id_info INTEGER NULL,
+                message_id_location INTEGER NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            "CREATE INDEX notification_outbox_region ON notification_outbox (region)",
+        ],
+    },
+    Migration {
+        version: 12,
+        description: "Store the full info of known pois",
+        mysql: &["ALTER TABLE known_blitzer ADD COLUMN info_json TEXT NULL AFTER info_desc"],
+        sqlite: &["ALTER TABLE known_blitzer ADD COLUMN info_json TEXT NULL"],
+    },
+];
+
+pub fn pending_migrations(storage: &mut dyn Storage) -> anyhow::Result<Vec<&'static Migration>> {
+    let current_version = storage.schema_version()?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+        .collect())
+}
+
+pub fn run(storage: &mut dyn Storage) -> anyhow::Result<()> {
+    for migration in pending_migrations(storage)? {
+        println!("Applying migration {}: {}", migration.version, migration.description);
+        storage.apply_migration(migration)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,612 @@
+use crate::database::migrations::{Dialect, Migration};
+use crate::database::{
+    parse_column, ActivePoi, Delivery, DeliveryEvent, DeliveryState, KnownPoi, MessageIds, PoiChange, PoiEvent, PoiEventType, PoiType,
+    Storage, StorageError, StorageResult,
+};
+use crate::model::{Address, DetailedPoi};
+use chrono::NaiveDateTime;
+use mysql::prelude::Queryable;
+use mysql::prelude::FromValue;
+use mysql::{params, Pool, PooledConn, Row, TxOpts};
+
+const KNOWN_POI_COLUMNS: &str =
+    "id,backend,chat_id,message_id_info,message_id_location,\
+    address_country,address_state,address_zip_code,address_city,address_city_district,address_street,\
+    poi_type,lat,lng,vmax,create_date,confirm_date,info_desc,last_seen,missed_runs,first_missed_at";
+
+fn known_poi_from_row(mut row: Row) -> StorageResult<KnownPoi> {
+    Ok(KnownPoi {
+        id: take(&mut row, "id")?,
+        backend_id: take(&mut row, "backend")?,
+        message_ids: MessageIds::from_columns(
+            take(&mut row, "chat_id")?,
+            take(&mut row, "message_id_info")?,
+            take(&mut row, "message_id_location")?,
+        ),
+        address: Address {
+            country: take(&mut row, "address_country")?,
+            state: take(&mut row, "address_state")?,
+            zip_code: take(&mut row, "address_zip_code")?,
+            city: take(&mut row, "address_city")?,
+            city_district: take(&mut row, "address_city_district")?,
+            street: take(&mut row, "address_street")?,
+        },
+        poi_type: take(&mut row, "poi_type")?,
+        lat: take(&mut row, "lat")?,
+        lng: take(&mut row, "lng")?,
+        vmax: take(&mut row, "vmax")?,
+        create_date: take(&mut row, "create_date")?,
+        confirm_date: take(&mut row, "confirm_date")?,
+        info_desc: take(&mut row, "info_desc")?,
+        last_seen: take(&mut row, "last_seen")?,
+        missed_runs: take(&mut row, "missed_runs")?,
+        first_missed_at: take(&mut row, "first_missed_at")?,
+    })
+}
+
+fn take<T: FromValue>(row: &mut Row, column: &str) -> StorageResult<T> {
+    let decode_error = |error: String| StorageError::Decode {
+        column: column.to_owned(),
+        error,
+    };
+
+    match row.take_opt(column) {
+        Some(value) => value.map_err(|error| decode_error(error.to_string())),
+        None => Err(decode_error(String::from("column is not selected"))),
+    }
+}
+
+pub struct MysqlRepository {
+    pool: Pool,
+}
+
+impl MysqlRepository {
+    pub fn try_new(connection_uri: &str) -> anyhow::Result<Self> {
+        let pool = Pool::new(connection_uri)?;
+
+        Ok(Self { pool })
+    }
+
+    fn connection(&self) -> StorageResult<PooledConn> {
+        Ok(self.pool.get_conn()?)
+    }
+}
+
+impl Storage for MysqlRepository {
+    fn dialect(&self) -> Dialect {
+        Dialect::Mysql
+    }
+
+    fn schema_version(&mut self) -> anyhow::Result<u32> {
+        let mut connection = self.connection()?;
+
+        let table_exists = |connection: &mut PooledConn, table: &str| -> anyhow::Result<bool> {
+            let count: Option<u32> = connection.exec_first(
+                "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = :table",
+                params! { "table" => table },
+            )?;
+            Ok(count.unwrap_or(0) > 0)
+        };
+
+        if table_exists(&mut connection, "schema_version")? {
+            let version: Option<Option<u32>> = connection.query_first("SELECT MAX(version) FROM schema_version")?;
+            return Ok(version.flatten().unwrap_or(0));
+        }
+
+        // Installations from before the migrations only have the known_blitzer table
+        if !table_exists(&mut connection, "known_blitzer")? {
+            return Ok(0);
+        }
+        let region_columns: Option<u32> = connection.query_first(
+            "SELECT COUNT(*) FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = 'known_blitzer' AND column_name = 'region'",
+        )?;
+
+        Ok(if region_columns.unwrap_or(0) > 0 { 2 } else { 1 })
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> anyhow::Result<()> {
+        let mut connection = self.connection()?;
+
+        connection.query_drop(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INT NOT NULL PRIMARY KEY,
+                description VARCHAR(255) NOT NULL,
+                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+        )?;
+
+        // MySQL commits every DDL statement on its own, so a migration can't run in a transaction. The finished
+        // statements are recorded instead and a migration which failed halfway continues after the last of them.
+        connection.query_drop(
+            "CREATE TABLE IF NOT EXISTS schema_migration_steps (
+                version INT NOT NULL,
+                step INT NOT NULL,
+                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (version, step)
+            )",
+        )?;
+        let applied_steps: Vec<usize> = connection.exec(
+            "SELECT step FROM schema_migration_steps WHERE version = :version",
+            params! {
+                "version" => migration.version,
+            },
+        )?;
+
+        for (step, statement) in migration.statements(&self.dialect()).iter().enumerate() {
+            if applied_steps.contains(&step) {
+                println!("Step {} of migration {} was already applied.. skipped", step + 1, migration.version);
+                continue;
+            }
+
+            connection.query_drop(statement)?;
+            connection.exec_drop(
+                "INSERT INTO schema_migration_steps (version, step) VALUES (:version, :step)",
+                params! {
+                    "version" => migration.version,
+                    "step" => step,
+                },
+            )?;
+        }
+
+        let mut transaction = connection.start_transaction(TxOpts::default())?;
+        transaction.exec_drop(
+            "INSERT INTO schema_version (version, description) VALUES (:version, :description)",
+            params! {
+                "version" => migration.version,
+                "description" => migration.description,
+            },
+        )?;
+        transaction.exec_drop(
+            "DELETE FROM schema_migration_steps WHERE version = :version",
+            params! {
+                "version" => migration.version,
+            },
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn add_poi(
+        &mut self,
+        region: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"INSERT INTO known_blitzer (
+                    id, region, lat, lng, address_country, address_state, address_zip_code, address_city,
+                    address_city_district, address_street, content, backend, poi_type, vmax,
+                    create_date, confirm_date, info_desc, info_json, chat_id, message_id_info, message_id_location
+                ) VALUES (
+                    :id, :region, :lat, :lng, :address_country, :address_state, :address_zip_code, :address_city,
+                    :address_city_district, :address_street, :content, :backend, :poi_type, :vmax,
+                    :create_date, :confirm_date, :info_desc, :info_json, :chat_id, :message_id_info, :message_id_location
+                )",
+            params! {
+                "id" => poi.id,
+                "region" => region,
+                "lat" => poi.lat,
+                "lng" => poi.lng,
+                "address_country" => poi.address.country,
+                "address_state" => poi.address.state,
+                "address_zip_code" => poi.address.zip_code,
+                "address_city" => poi.address.city,
+                "address_city_district" => poi.address.city_district,
+                "address_street" => poi.address.street,
+                "content" => poi.content,
+                "backend" => poi.backend,
+                "poi_type" => poi.poi_type,
+                "vmax" => poi.vmax,
+                "create_date" => poi.create_date,
+                "confirm_date" => poi.confirm_date,
+                "info_desc" => poi.info.desc.as_deref(),
+                "info_json" => poi.info.to_json(),
+                "chat_id" => message_ids.map(|message_ids| message_ids.chat_id),
+                "message_id_info" => message_ids.map(|message_ids| message_ids.info),
+                "message_id_location" => message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn get_known_pois(&mut self, region: &str) -> StorageResult<Vec<KnownPoi>> {
+        self.connection()?
+            .exec_map(
+                format!("SELECT {KNOWN_POI_COLUMNS} from known_blitzer WHERE last_seen IS NULL AND region = :region"),
+                params! {
+                    "region" => region,
+                },
+                known_poi_from_row,
+            )?
+            .into_iter()
+            .collect()
+    }
+
+    fn find_inactive_poi(&mut self, region: &str, poi_id: &str, backend_id: &str) -> StorageResult<Option<KnownPoi>> {
+        let mut inactive_pois = self.connection()?
+            .exec_map(
+                format!(
+                    "SELECT {KNOWN_POI_COLUMNS} from known_blitzer
+                        WHERE last_seen IS NOT NULL AND region = :region AND (id = :id OR backend = :backend)
+                        ORDER BY id = :id DESC, last_seen DESC LIMIT 1"
+                ),
+                params! {
+                    "region" => region,
+                    "id" => poi_id,
+                    "backend" => backend_id,
+                },
+                known_poi_from_row,
+            )?;
+
+        inactive_pois.pop().transpose()
+    }
+
+    fn reactivate_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()> {
+        let new_poi_id = poi.id.clone();
+        let mut connection = self.connection()?;
+        let mut transaction = connection.start_transaction(TxOpts::default())?;
+
+        transaction.exec_drop(
+            r"UPDATE known_blitzer SET
+                    id = :new_id, lat = :lat, lng = :lng, address_country = :address_country, address_state = :address_state,
+                    address_zip_code = :address_zip_code, address_city = :address_city, address_city_district = :address_city_district,
+                    address_street = :address_street, content = :content, backend = :backend, poi_type = :poi_type, vmax = :vmax,
+                    create_date = :create_date, confirm_date = :confirm_date, info_desc = :info_desc, info_json = :info_json, last_seen = NULL, missed_runs = 0, first_missed_at = NULL,
+                    chat_id = :chat_id, message_id_info = :message_id_info, message_id_location = :message_id_location
+                WHERE id = :id AND region = :region",
+            params! {
+                "id" => poi_id,
+                "region" => region,
+                "new_id" => poi.id,
+                "lat" => poi.lat,
+                "lng" => poi.lng,
+                "address_country" => poi.address.country,
+                "address_state" => poi.address.state,
+                "address_zip_code" => poi.address.zip_code,
+                "address_city" => poi.address.city,
+                "address_city_district" => poi.address.city_district,
+                "address_street" => poi.address.street,
+                "content" => poi.content,
+                "backend" => poi.backend,
+                "poi_type" => poi.poi_type,
+                "vmax" => poi.vmax,
+                "create_date" => poi.create_date,
+                "confirm_date" => poi.confirm_date,
+                "info_desc" => poi.info.desc.as_deref(),
+                "info_json" => poi.info.to_json(),
+                "chat_id" => message_ids.map(|message_ids| message_ids.chat_id),
+                "message_id_info" => message_ids.map(|message_ids| message_ids.info),
+                "message_id_location" => message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+
+        // Keep the history of the poi, even if the api assigned a new id
+        for table in ["poi_changes", "poi_events"] {
+            transaction.exec_drop(
+                format!("UPDATE {table} SET poi_id = :new_id WHERE poi_id = :id AND region = :region"),
+                params! {
+                    "id" => poi_id,
+                    "region" => region,
+                    "new_id" => &new_poi_id,
+                }
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn update_last_seen(&mut self, region: &str, poi_id: String) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"UPDATE known_blitzer SET last_seen = CURRENT_TIMESTAMP() WHERE id = :id AND region = :region",
+            params! {
+                "id" => poi_id,
+                "region" => region,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn update_missed(&mut self, region: &str, poi_id: &str, missed_runs: u32, first_missed_at: Option<NaiveDateTime>) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"UPDATE known_blitzer SET missed_runs = :missed_runs, first_missed_at = :first_missed_at WHERE id = :id AND region = :region",
+            params! {
+                "id" => poi_id,
+                "region" => region,
+                "missed_runs" => missed_runs,
+                "first_missed_at" => first_missed_at,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn update_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: &DetailedPoi,
+        message_ids: Option<&MessageIds>,
+        changes: &[PoiChange],
+    ) -> StorageResult<()> {
+        let mut connection = self.connection()?;
+        let mut transaction = connection.start_transaction(TxOpts::default())?;
+
+        transaction.exec_drop(
+            r"UPDATE known_blitzer SET
+                    lat = :lat, lng = :lng, vmax = :vmax, create_date = :create_date, confirm_date = :confirm_date,
+                    info_desc = :info_desc, info_json = :info_json, chat_id = :chat_id, message_id_info = :message_id_info,
+                    message_id_location = :message_id_location
+                WHERE id = :id AND region = :region",
+            params! {
+                "id" => poi_id,
+                "region" => region,
+                "lat" => poi.lat,
+                "lng" => poi.lng,
+                "vmax" => poi.vmax,
+                "create_date" => poi.create_date,
+                "confirm_date" => poi.confirm_date,
+                "info_desc" => poi.info.desc.as_deref(),
+                "info_json" => poi.info.to_json(),
+                "chat_id" => message_ids.map(|message_ids| message_ids.chat_id),
+                "message_id_info" => message_ids.map(|message_ids| message_ids.info),
+                "message_id_location" => message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+
+        transaction.exec_batch(
+            r"INSERT INTO poi_changes (poi_id, region, field, old_value, new_value)
+                VALUES (:poi_id, :region, :field, :old_value, :new_value)",
+            changes.iter().map(|change| params! {
+                "poi_id" => poi_id,
+                "region" => region,
+                "field" => change.field,
+                "old_value" => change.old_value.as_deref(),
+                "new_value" => change.new_value.as_deref(),
+            })
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn add_event(&mut self, region: &str, poi_id: &str, event_type: PoiEventType, details: Option<String>) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"INSERT INTO poi_events (poi_id, region, event_type, details) VALUES (:poi_id, :region, :event_type, :details)",
+            params! {
+                "poi_id" => poi_id,
+                "region" => region,
+                "event_type" => event_type.to_string(),
+                "details" => details,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn get_timeline(&mut self, backend_id: &str) -> StorageResult<Vec<PoiEvent>> {
+        self.connection()?
+            .exec_map(
+                r"SELECT poi_events.region, poi_events.poi_id, poi_events.event_type, poi_events.details, poi_events.created_at
+                    FROM poi_events
+                    JOIN known_blitzer ON known_blitzer.id = poi_events.poi_id AND known_blitzer.region = poi_events.region
+                    WHERE known_blitzer.backend = :backend
+                    ORDER BY poi_events.created_at, poi_events.id",
+                params! {
+                    "backend" => backend_id,
+                },
+                |(region, poi_id, event_type, details, created_at): (String, String, String, Option<String>, NaiveDateTime)| {
+                    Ok(PoiEvent {
+                        region,
+                        poi_id,
+                        event_type: parse_column("event_type", event_type)?,
+                        details,
+                        created_at,
+                    })
+                },
+            )?
+            .into_iter()
+            .collect()
+    }
+
+    fn get_active_pois(&mut self) -> StorageResult<Vec<ActivePoi>> {
+        self.connection()?
+            .query_map(
+                "SELECT region,backend,lat,lng,vmax,poi_type,address_zip_code,address_city,address_street,first_seen
+                    FROM known_blitzer WHERE last_seen IS NULL ORDER BY region, first_seen",
+                |mut row: Row| -> StorageResult<ActivePoi> {
+                    Ok(ActivePoi {
+                        region: take(&mut row, "region")?,
+                        backend_id: take(&mut row, "backend")?,
+                        lat: take(&mut row, "lat")?,
+                        lng: take(&mut row, "lng")?,
+                        vmax: take(&mut row, "vmax")?,
+                        poi_type: take(&mut row, "poi_type")?,
+                        address_zip_code: take(&mut row, "address_zip_code")?,
+                        address_city: take(&mut row, "address_city")?,
+                        address_street: take(&mut row, "address_street")?,
+                        first_seen: take(&mut row, "first_seen")?,
+                    })
+                },
+            )?
+            .into_iter()
+            .collect()
+    }
+
+    fn set_muted_until(&mut self, chat_id: &str, muted_until: Option<NaiveDateTime>) -> StorageResult<()> {
+        let mut connection = self.connection()?;
+
+        match muted_until {
+            Some(muted_until) => connection.exec_drop(
+                r"INSERT INTO chat_mutes (chat_id, muted_until) VALUES (:chat_id, :muted_until)
+                    ON DUPLICATE KEY UPDATE muted_until = VALUES(muted_until)",
+                params! {
+                    "chat_id" => chat_id,
+                    "muted_until" => muted_until,
+                },
+            ),
+            None => connection.exec_drop(
+                r"DELETE FROM chat_mutes WHERE chat_id = :chat_id",
+                params! {
+                    "chat_id" => chat_id,
+                },
+            ),
+        }?;
+        Ok(())
+    }
+
+    fn get_muted_until(&mut self, chat_id: &str) -> StorageResult<Option<NaiveDateTime>> {
+        self.connection()?
+            .exec_first(
+                r"SELECT muted_until FROM chat_mutes WHERE chat_id = :chat_id",
+                params! {
+                    "chat_id" => chat_id,
+                },
+            )
+            .map_err(StorageError::from)
+    }
+
+    fn add_dead_letter(&mut self, notifier: &str, url: &str, payload: &str, error: &str) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"INSERT INTO webhook_dead_letters (notifier, url, payload, error) VALUES (:notifier, :url, :payload, :error)",
+            params! {
+                "notifier" => notifier,
+                "url" => url,
+                "payload" => payload,
+                "error" => error,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn record_poi_type(&mut self, code: &str, backend_id: &str) -> StorageResult<bool> {
+        let mut connection = self.connection()?;
+
+        let is_known = connection
+            .exec_first::<i32, _, _>(
+                r"SELECT 1 FROM poi_types WHERE code = :code",
+                params! {
+                    "code" => code,
+                },
+            )?
+            .is_some();
+
+        connection.exec_drop(
+            r"INSERT INTO poi_types (code, backend_id) VALUES (:code, :backend_id)
+                ON DUPLICATE KEY UPDATE backend_id = VALUES(backend_id), last_seen = CURRENT_TIMESTAMP()",
+            params! {
+                "code" => code,
+                "backend_id" => backend_id,
+            }
+        )?;
+
+        Ok(!is_known)
+    }
+
+    fn get_poi_types(&mut self) -> StorageResult<Vec<PoiType>> {
+        self.connection()?
+            .query_map(
+                "SELECT code, backend_id, first_seen, last_seen FROM poi_types ORDER BY first_seen, code",
+                |(code, backend_id, first_seen, last_seen)| PoiType {
+                    code,
+                    backend_id,
+                    first_seen,
+                    last_seen,
+                },
+            )
+            .map_err(StorageError::from)
+    }
+
+    fn get_deliveries(&mut self, region: &str) -> StorageResult<Vec<Delivery>> {
+        self.connection()?
+            .exec_map(
+                r"SELECT id, backend_id, event, notifier, details, state, chat_id, message_id_info, message_id_location
+                    FROM notification_outbox WHERE region = :region ORDER BY id",
+                params! {
+                    "region" => region,
+                },
+                |mut row: Row| -> StorageResult<Delivery> {
+                    Ok(Delivery {
+                        id: take(&mut row, "id")?,
+                        backend_id: take(&mut row, "backend_id")?,
+                        event: parse_column("event", take(&mut row, "event")?)?,
+                        notifier: take(&mut row, "notifier")?,
+                        details: take(&mut row, "details")?,
+                        state: parse_column("state", take(&mut row, "state")?)?,
+                        message_ids: MessageIds::from_columns(
+                            take(&mut row, "chat_id")?,
+                            take(&mut row, "message_id_info")?,
+                            take(&mut row, "message_id_location")?,
+                        ),
+                    })
+                },
+            )?
+            .into_iter()
+            .collect()
+    }
+
+    fn add_delivery(
+        &mut self,
+        region: &str,
+        backend_id: &str,
+        event: DeliveryEvent,
+        notifier: &str,
+        details: &str,
+    ) -> StorageResult<i64> {
+        let mut connection = self.connection()?;
+        connection.exec_drop(
+            r"INSERT INTO notification_outbox (region, backend_id, event, notifier, details, state)
+                VALUES (:region, :backend_id, :event, :notifier, :details, :state)",
+            params! {
+                "region" => region,
+                "backend_id" => backend_id,
+                "event" => event.to_string(),
+                "notifier" => notifier,
+                "details" => details,
+                "state" => DeliveryState::Pending.to_string(),
+            }
+        )?;
+        Ok(connection.last_insert_id() as i64)
+    }
+
+    fn update_delivery(&mut self, id: i64, state: DeliveryState, message_ids: Option<&MessageIds>) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"UPDATE notification_outbox SET
+                    state = :state, chat_id = :chat_id, message_id_info = :message_id_info,
+                    message_id_location = :message_id_location, updated_at = CURRENT_TIMESTAMP()
+                WHERE id = :id",
+            params! {
+                "id" => id,
+                "state" => state.to_string(),
+                "chat_id" => message_ids.map(|message_ids| message_ids.chat_id),
+                "message_id_info" => message_ids.map(|message_ids| message_ids.info),
+                "message_id_location" => message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn persist_deliveries(&mut self, region: &str, backend_id: &str) -> StorageResult<()> {
+        self.connection()?.exec_drop(
+            r"UPDATE notification_outbox SET state = :persisted, updated_at = CURRENT_TIMESTAMP()
+                WHERE region = :region AND backend_id = :backend_id AND state = :sent",
+            params! {
+                "region" => region,
+                "backend_id" => backend_id,
+                "persisted" => DeliveryState::Persisted.to_string(),
+                "sent" => DeliveryState::Sent.to_string(),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn delete_delivery(&mut self, id: i64) -> StorageResult<()> {
+        self.connection()?.exec_drop(r"DELETE FROM notification_outbox WHERE id = :id", params! { "id" => id })?;
+        Ok(())
+    }
+}
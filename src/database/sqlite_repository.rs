@@ -0,0 +1,594 @@
+use crate::database::migrations::{Dialect, Migration};
+use crate::database::{
+    parse_column, ActivePoi, Delivery, DeliveryEvent, DeliveryState, KnownPoi, MessageIds, PoiChange, PoiEvent, PoiEventType, PoiType,
+    Storage, StorageError, StorageResult,
+};
+use crate::model::{Address, DetailedPoi};
+use chrono::{Local, NaiveDateTime};
+use rusqlite::types::Type;
+use rusqlite::{named_params, Connection, OptionalExtension, Row};
+use std::str::FromStr;
+
+const KNOWN_POI_COLUMNS: &str =
+    "id,backend,chat_id,message_id_info,message_id_location,\
+    address_country,address_state,address_zip_code,address_city,address_city_district,address_street,\
+    poi_type,lat,lng,vmax,create_date,confirm_date,info_desc,last_seen,missed_runs,first_missed_at";
+
+fn known_poi_from_row(row: &Row) -> rusqlite::Result<KnownPoi> {
+    Ok(KnownPoi {
+        id: row.get("id")?,
+        backend_id: row.get("backend")?,
+        message_ids: MessageIds::from_columns(
+            row.get("chat_id")?,
+            row.get("message_id_info")?,
+            row.get("message_id_location")?,
+        ),
+        address: Address {
+            country: row.get("address_country")?,
+            state: row.get("address_state")?,
+            zip_code: row.get("address_zip_code")?,
+            city: row.get("address_city")?,
+            city_district: row.get("address_city_district")?,
+            street: row.get("address_street")?,
+        },
+        poi_type: row.get("poi_type")?,
+        lat: row.get("lat")?,
+        lng: row.get("lng")?,
+        vmax: row.get("vmax")?,
+        create_date: row.get("create_date")?,
+        confirm_date: row.get("confirm_date")?,
+        info_desc: row.get("info_desc")?,
+        last_seen: row.get("last_seen")?,
+        missed_runs: row.get("missed_runs")?,
+        first_missed_at: row.get("first_missed_at")?,
+    })
+}
+
+// CURRENT_TIMESTAMP of SQLite is in UTC, so the local time is bound like MySQL stores it
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+// The decode error is passed through rusqlite and unwrapped again when it is converted into a storage error
+fn parse_text<T: FromStr>(row: &Row, column: &str) -> rusqlite::Result<T> {
+    parse_column(column, row.get(column)?).map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(row.as_ref().column_index(column).unwrap_or_default(), Type::Text, Box::new(error))
+    })
+}
+
+pub struct SqliteRepository {
+    connection: Connection,
+}
+
+impl SqliteRepository {
+    pub fn try_new(path: &str) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)?;
+
+        Ok(Self { connection })
+    }
+}
+
+impl Storage for SqliteRepository {
+    fn dialect(&self) -> Dialect {
+        Dialect::Sqlite
+    }
+
+    fn schema_version(&mut self) -> anyhow::Result<u32> {
+        let table_exists = |connection: &Connection, table: &str| -> anyhow::Result<bool> {
+            let count: u32 = connection.query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = :table",
+                named_params! { ":table": table },
+                |row| row.get(0),
+            )?;
+            Ok(count > 0)
+        };
+
+        if table_exists(&self.connection, "schema_version")? {
+            let version: Option<u32> =
+                self.connection.query_row("SELECT MAX(version) FROM schema_version", (), |row| row.get(0))?;
+            return Ok(version.unwrap_or(0));
+        }
+
+        // Installations from before the migrations only have the known_blitzer table
+        if !table_exists(&self.connection, "known_blitzer")? {
+            return Ok(0);
+        }
+        let region_columns: u32 = self.connection.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('known_blitzer') WHERE name = 'region'",
+            (),
+            |row| row.get(0),
+        )?;
+
+        Ok(if region_columns > 0 { 2 } else { 1 })
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> anyhow::Result<()> {
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            "CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER NOT NULL PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            (),
+        )?;
+
+        for statement in migration.statements(&Dialect::Sqlite) {
+            transaction.execute(statement, ())?;
+        }
+
+        transaction.execute(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (:version, :description, :now)",
+            named_params! {
+                ":version": migration.version,
+                ":description": migration.description,
+                ":now": now(),
+            },
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn add_poi(
+        &mut self,
+        region: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()> {
+        self.connection.execute(
+            r"INSERT INTO known_blitzer (
+                    id, region, lat, lng, address_country, address_state, address_zip_code, address_city,
+                    address_city_district, address_street, content, backend, poi_type, vmax,
+                    create_date, confirm_date, info_desc, info_json, first_seen, chat_id, message_id_info, message_id_location
+                ) VALUES (
+                    :id, :region, :lat, :lng, :address_country, :address_state, :address_zip_code, :address_city,
+                    :address_city_district, :address_street, :content, :backend, :poi_type, :vmax,
+                    :create_date, :confirm_date, :info_desc, :info_json, :now, :chat_id, :message_id_info, :message_id_location
+                )",
+            named_params! {
+                ":now": now(),
+                ":id": poi.id,
+                ":region": region,
+                ":lat": poi.lat,
+                ":lng": poi.lng,
+                ":address_country": poi.address.country,
+                ":address_state": poi.address.state,
+                ":address_zip_code": poi.address.zip_code,
+                ":address_city": poi.address.city,
+                ":address_city_district": poi.address.city_district,
+                ":address_street": poi.address.street,
+                ":content": poi.content,
+                ":backend": poi.backend,
+                ":poi_type": poi.poi_type,
+                ":vmax": poi.vmax,
+                ":create_date": poi.create_date,
+                ":confirm_date": poi.confirm_date,
+                ":info_desc": poi.info.desc.as_deref(),
+                ":info_json": poi.info.to_json(),
+                ":chat_id": message_ids.map(|message_ids| message_ids.chat_id),
+                ":message_id_info": message_ids.map(|message_ids| message_ids.info),
+                ":message_id_location": message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn get_known_pois(&mut self, region: &str) -> StorageResult<Vec<KnownPoi>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!(
+                "SELECT {KNOWN_POI_COLUMNS} from known_blitzer WHERE last_seen IS NULL AND region = :region"
+            ))?;
+
+        let known_blitzer: Vec<KnownPoi> = statement
+            .query_map(named_params! { ":region": region }, known_poi_from_row)
+            .and_then(|rows| rows.collect())?;
+
+        Ok(known_blitzer)
+    }
+
+    fn find_inactive_poi(&mut self, region: &str, poi_id: &str, backend_id: &str) -> StorageResult<Option<KnownPoi>> {
+        self.connection
+            .query_row(
+                &format!(
+                    "SELECT {KNOWN_POI_COLUMNS} from known_blitzer
+                        WHERE last_seen IS NOT NULL AND region = :region AND (id = :id OR backend = :backend)
+                        ORDER BY id = :id DESC, last_seen DESC LIMIT 1"
+                ),
+                named_params! {
+                    ":region": region,
+                    ":id": poi_id,
+                    ":backend": backend_id,
+                },
+                known_poi_from_row,
+            )
+            .optional()
+            .map_err(StorageError::from)
+    }
+
+    fn reactivate_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: DetailedPoi,
+        message_ids: Option<&MessageIds>,
+    ) -> StorageResult<()> {
+        let new_poi_id = poi.id.clone();
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            r"UPDATE known_blitzer SET
+                    id = :new_id, lat = :lat, lng = :lng, address_country = :address_country, address_state = :address_state,
+                    address_zip_code = :address_zip_code, address_city = :address_city, address_city_district = :address_city_district,
+                    address_street = :address_street, content = :content, backend = :backend, poi_type = :poi_type, vmax = :vmax,
+                    create_date = :create_date, confirm_date = :confirm_date, info_desc = :info_desc, info_json = :info_json, last_seen = NULL, missed_runs = 0, first_missed_at = NULL,
+                    chat_id = :chat_id, message_id_info = :message_id_info, message_id_location = :message_id_location
+                WHERE id = :id AND region = :region",
+            named_params! {
+                ":id": poi_id,
+                ":region": region,
+                ":new_id": poi.id,
+                ":lat": poi.lat,
+                ":lng": poi.lng,
+                ":address_country": poi.address.country,
+                ":address_state": poi.address.state,
+                ":address_zip_code": poi.address.zip_code,
+                ":address_city": poi.address.city,
+                ":address_city_district": poi.address.city_district,
+                ":address_street": poi.address.street,
+                ":content": poi.content,
+                ":backend": poi.backend,
+                ":poi_type": poi.poi_type,
+                ":vmax": poi.vmax,
+                ":create_date": poi.create_date,
+                ":confirm_date": poi.confirm_date,
+                ":info_desc": poi.info.desc.as_deref(),
+                ":info_json": poi.info.to_json(),
+                ":chat_id": message_ids.map(|message_ids| message_ids.chat_id),
+                ":message_id_info": message_ids.map(|message_ids| message_ids.info),
+                ":message_id_location": message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+
+        // Keep the history of the poi, even if the api assigned a new id
+        for table in ["poi_changes", "poi_events"] {
+            transaction.execute(
+                &format!("UPDATE {table} SET poi_id = :new_id WHERE poi_id = :id AND region = :region"),
+                named_params! {
+                    ":id": poi_id,
+                    ":region": region,
+                    ":new_id": &new_poi_id,
+                }
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn update_last_seen(&mut self, region: &str, poi_id: String) -> StorageResult<()> {
+        self.connection.execute(
+            r"UPDATE known_blitzer SET last_seen = :now WHERE id = :id AND region = :region",
+            named_params! {
+                ":now": now(),
+                ":id": poi_id,
+                ":region": region,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn update_missed(&mut self, region: &str, poi_id: &str, missed_runs: u32, first_missed_at: Option<NaiveDateTime>) -> StorageResult<()> {
+        self.connection.execute(
+            r"UPDATE known_blitzer SET missed_runs = :missed_runs, first_missed_at = :first_missed_at WHERE id = :id AND region = :region",
+            named_params! {
+                ":id": poi_id,
+                ":region": region,
+                ":missed_runs": missed_runs,
+                ":first_missed_at": first_missed_at,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn update_poi(
+        &mut self,
+        region: &str,
+        poi_id: &str,
+        poi: &DetailedPoi,
+        message_ids: Option<&MessageIds>,
+        changes: &[PoiChange],
+    ) -> StorageResult<()> {
+        let transaction = self.connection.transaction()?;
+
+        transaction.execute(
+            r"UPDATE known_blitzer SET
+                    lat = :lat, lng = :lng, vmax = :vmax, create_date = :create_date, confirm_date = :confirm_date,
+                    info_desc = :info_desc, info_json = :info_json, chat_id = :chat_id, message_id_info = :message_id_info,
+                    message_id_location = :message_id_location
+                WHERE id = :id AND region = :region",
+            named_params! {
+                ":id": poi_id,
+                ":region": region,
+                ":lat": poi.lat,
+                ":lng": poi.lng,
+                ":vmax": poi.vmax,
+                ":create_date": poi.create_date,
+                ":confirm_date": poi.confirm_date,
+                ":info_desc": poi.info.desc.as_deref(),
+                ":info_json": poi.info.to_json(),
+                ":chat_id": message_ids.map(|message_ids| message_ids.chat_id),
+                ":message_id_info": message_ids.map(|message_ids| message_ids.info),
+                ":message_id_location": message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+
+        for change in changes {
+            transaction.execute(
+                r"INSERT INTO poi_changes (poi_id, region, field, old_value, new_value, changed_at)
+                    VALUES (:poi_id, :region, :field, :old_value, :new_value, :now)",
+                named_params! {
+                    ":now": now(),
+                    ":poi_id": poi_id,
+                    ":region": region,
+                    ":field": change.field,
+                    ":old_value": change.old_value.as_deref(),
+                    ":new_value": change.new_value.as_deref(),
+                }
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn add_event(&mut self, region: &str, poi_id: &str, event_type: PoiEventType, details: Option<String>) -> StorageResult<()> {
+        self.connection.execute(
+            r"INSERT INTO poi_events (poi_id, region, event_type, details, created_at)
+                VALUES (:poi_id, :region, :event_type, :details, :now)",
+            named_params! {
+                ":now": now(),
+                ":poi_id": poi_id,
+                ":region": region,
+                ":event_type": event_type.to_string(),
+                ":details": details,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn get_timeline(&mut self, backend_id: &str) -> StorageResult<Vec<PoiEvent>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                r"SELECT poi_events.region, poi_events.poi_id, poi_events.event_type, poi_events.details, poi_events.created_at
+                    FROM poi_events
+                    JOIN known_blitzer ON known_blitzer.id = poi_events.poi_id AND known_blitzer.region = poi_events.region
+                    WHERE known_blitzer.backend = :backend
+                    ORDER BY poi_events.created_at, poi_events.id",
+            )?;
+
+        statement
+            .query_map(named_params! { ":backend": backend_id }, |row| {
+                Ok(PoiEvent {
+                    region: row.get(0)?,
+                    poi_id: row.get(1)?,
+                    event_type: parse_text(row, "event_type")?,
+                    details: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(StorageError::from)
+    }
+
+    fn get_active_pois(&mut self) -> StorageResult<Vec<ActivePoi>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT region,backend,lat,lng,vmax,poi_type,address_zip_code,address_city,address_street,first_seen
+                    FROM known_blitzer WHERE last_seen IS NULL ORDER BY region, first_seen",
+            )?;
+
+        statement
+            .query_map((), |row| {
+                Ok(ActivePoi {
+                    region: row.get("region")?,
+                    backend_id: row.get("backend")?,
+                    lat: row.get("lat")?,
+                    lng: row.get("lng")?,
+                    vmax: row.get("vmax")?,
+                    poi_type: row.get("poi_type")?,
+                    address_zip_code: row.get("address_zip_code")?,
+                    address_city: row.get("address_city")?,
+                    address_street: row.get("address_street")?,
+                    first_seen: row.get("first_seen")?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(StorageError::from)
+    }
+
+    fn set_muted_until(&mut self, chat_id: &str, muted_until: Option<NaiveDateTime>) -> StorageResult<()> {
+        match muted_until {
+            Some(muted_until) => self.connection.execute(
+                r"INSERT INTO chat_mutes (chat_id, muted_until) VALUES (:chat_id, :muted_until)
+                    ON CONFLICT (chat_id) DO UPDATE SET muted_until = excluded.muted_until",
+                named_params! {
+                    ":chat_id": chat_id,
+                    ":muted_until": muted_until,
+                },
+            ),
+            None => self.connection.execute(
+                r"DELETE FROM chat_mutes WHERE chat_id = :chat_id",
+                named_params! {
+                    ":chat_id": chat_id,
+                },
+            ),
+        }?;
+        Ok(())
+    }
+
+    fn get_muted_until(&mut self, chat_id: &str) -> StorageResult<Option<NaiveDateTime>> {
+        self.connection
+            .query_row(
+                r"SELECT muted_until FROM chat_mutes WHERE chat_id = :chat_id",
+                named_params! {
+                    ":chat_id": chat_id,
+                },
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(StorageError::from)
+    }
+
+    fn add_dead_letter(&mut self, notifier: &str, url: &str, payload: &str, error: &str) -> StorageResult<()> {
+        self.connection.execute(
+            r"INSERT INTO webhook_dead_letters (notifier, url, payload, error, created_at)
+                VALUES (:notifier, :url, :payload, :error, :now)",
+            named_params! {
+                ":now": now(),
+                ":notifier": notifier,
+                ":url": url,
+                ":payload": payload,
+                ":error": error,
+            }
+        )?;
+        Ok(())
+    }
+
+    fn record_poi_type(&mut self, code: &str, backend_id: &str) -> StorageResult<bool> {
+        let is_known = self.connection
+            .query_row(
+                r"SELECT 1 FROM poi_types WHERE code = :code",
+                named_params! {
+                    ":code": code,
+                },
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+
+        self.connection.execute(
+            r"INSERT INTO poi_types (code, backend_id, first_seen, last_seen) VALUES (:code, :backend_id, :now, :now)
+                ON CONFLICT (code) DO UPDATE SET backend_id = excluded.backend_id, last_seen = excluded.last_seen",
+            named_params! {
+                ":now": now(),
+                ":code": code,
+                ":backend_id": backend_id,
+            }
+        )?;
+
+        Ok(!is_known)
+    }
+
+    fn get_poi_types(&mut self) -> StorageResult<Vec<PoiType>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT code, backend_id, first_seen, last_seen FROM poi_types ORDER BY first_seen, code")?;
+
+        statement
+            .query_map((), |row| {
+                Ok(PoiType {
+                    code: row.get(0)?,
+                    backend_id: row.get(1)?,
+                    first_seen: row.get(2)?,
+                    last_seen: row.get(3)?,
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(StorageError::from)
+    }
+
+    fn get_deliveries(&mut self, region: &str) -> StorageResult<Vec<Delivery>> {
+        let mut statement = self
+            .connection
+            .prepare(
+                r"SELECT id, backend_id, event, notifier, details, state, chat_id, message_id_info, message_id_location
+                    FROM notification_outbox WHERE region = :region ORDER BY id",
+            )?;
+
+        statement
+            .query_map(named_params! { ":region": region }, |row| {
+                Ok(Delivery {
+                    id: row.get("id")?,
+                    backend_id: row.get("backend_id")?,
+                    event: parse_text(row, "event")?,
+                    notifier: row.get("notifier")?,
+                    details: row.get("details")?,
+                    state: parse_text(row, "state")?,
+                    message_ids: MessageIds::from_columns(
+                        row.get("chat_id")?,
+                        row.get("message_id_info")?,
+                        row.get("message_id_location")?,
+                    ),
+                })
+            })
+            .and_then(|rows| rows.collect())
+            .map_err(StorageError::from)
+    }
+
+    fn add_delivery(
+        &mut self,
+        region: &str,
+        backend_id: &str,
+        event: DeliveryEvent,
+        notifier: &str,
+        details: &str,
+    ) -> StorageResult<i64> {
+        self.connection.execute(
+            r"INSERT INTO notification_outbox (region, backend_id, event, notifier, details, state, created_at, updated_at)
+                VALUES (:region, :backend_id, :event, :notifier, :details, :state, :now, :now)",
+            named_params! {
+                ":now": now(),
+                ":region": region,
+                ":backend_id": backend_id,
+                ":event": event.to_string(),
+                ":notifier": notifier,
+                ":details": details,
+                ":state": DeliveryState::Pending.to_string(),
+            }
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    fn update_delivery(&mut self, id: i64, state: DeliveryState, message_ids: Option<&MessageIds>) -> StorageResult<()> {
+        self.connection.execute(
+            r"UPDATE notification_outbox SET
+                    state = :state, chat_id = :chat_id, message_id_info = :message_id_info,
+                    message_id_location = :message_id_location, updated_at = :now
+                WHERE id = :id",
+            named_params! {
+                ":now": now(),
+                ":id": id,
+                ":state": state.to_string(),
+                ":chat_id": message_ids.map(|message_ids| message_ids.chat_id),
+                ":message_id_info": message_ids.map(|message_ids| message_ids.info),
+                ":message_id_location": message_ids.and_then(|message_ids| message_ids.location),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn persist_deliveries(&mut self, region: &str, backend_id: &str) -> StorageResult<()> {
+        self.connection.execute(
+            r"UPDATE notification_outbox SET state = :persisted, updated_at = :now
+                WHERE region = :region AND backend_id = :backend_id AND state = :sent",
+            named_params! {
+                ":now": now(),
+                ":region": region,
+                ":backend_id": backend_id,
+                ":persisted": DeliveryState::Persisted.to_string(),
+                ":sent": DeliveryState::Sent.to_string(),
+            }
+        )?;
+        Ok(())
+    }
+
+    fn delete_delivery(&mut self, id: i64) -> StorageResult<()> {
+        self.connection.execute(r"DELETE FROM notification_outbox WHERE id = :id", named_params! { ":id": id })?;
+        Ok(())
+    }
+}
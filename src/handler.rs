@@ -1,80 +1,277 @@
-use std::collections::HashMap;
-use crate::blitzer_api_client::get_blitzer_api_result;
-use crate::model::{BlitzerClientRequestParams, LocationType, Poi};
-use crate::telegram::TelegramBot;
-use crate::{configuration, database};
-use strum::IntoEnumIterator;
-use crate::database::KnownPoi;
-
-pub(crate) async fn handle(telegram_bot: &TelegramBot) -> Result<(), anyhow::Error> {
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use anyhow::anyhow;
+use chrono::Local;
+use crate::blitzer_api_client::ApiClient;
+use crate::configuration::{FailurePolicy, GraceConfiguration, Region};
+use crate::model::{DetailedPoi, LocationType, Poi};
+use crate::notifier::{Notifier, Notifiers};
+use crate::configuration;
+use crate::database::{DeliveryEvent, KnownPoi, PoiChange, PoiEventType, Storage};
+use crate::outbox::Outbox;
+
+pub(crate) async fn handle(
+    notifiers: &Notifiers,
+    api_client: &ApiClient,
+    database: &mut dyn Storage,
+) -> Result<(), anyhow::Error> {
     println!("Start BlitzerNotifier!");
 
-    let location_box = configuration::get_location_box().await;
-    println!("Working with locationBox: {}", location_box);
+    let regions = configuration::get_regions().await;
+    println!("Working with {} regions", regions.len());
+    let grace_configuration = configuration::get_grace_configuration().await;
+    let failure_policy = configuration::get_failure_policy().await;
 
-    println!("Init database connection...");
-    let mut database = database::Repository::try_new().await?;
+    for region in regions {
+        handle_region(notifiers, api_client, database, &grace_configuration, failure_policy, region).await?;
+    }
 
-    let request_params = BlitzerClientRequestParams {
-        zoom_level: 5,
-        types: LocationType::iter()
-            .filter(|location_type: &LocationType| location_type.is_default())
-            .collect(),
-        location_box,
-    };
+    Ok(())
+}
+
+async fn handle_region(
+    notifiers: &Notifiers,
+    api_client: &ApiClient,
+    database: &mut dyn Storage,
+    grace_configuration: &GraceConfiguration,
+    failure_policy: FailurePolicy,
+    region: Region,
+) -> Result<(), anyhow::Error> {
+    let region_notifiers = notifiers.for_region(&region);
+    let location_boxes = region.area.location_boxes();
+    println!("Working with region {} and {} location boxes", region.name, location_boxes.len());
 
-    let api_response = get_blitzer_api_result(&request_params).await?;
-    println!("Found {} pois in the given area", api_response.pois.len());
+    let mut pois = Vec::new();
+    for location_box in location_boxes {
+        println!("Requesting locationBox: {}", location_box);
+        let box_pois = api_client.get_pois(&region.types, location_box).await?;
+        println!("Found {} pois in the given area", box_pois.len());
+        pois.extend(box_pois);
+    }
 
-    let mut known_pois: HashMap<String, KnownPoi> = database.get_known_pois().into_iter()        
+    let mut known_pois: HashMap<String, KnownPoi> = database.get_known_pois(&region.name)?.into_iter()        
         .map(|known_poi| (known_poi.backend_id.clone(), known_poi)) 
         .collect();         
     println!("There are {} active pois in the database", known_pois.len());
 
+    let mut seen_backend_ids = HashSet::new();
+    let mut seen_types = HashSet::new();
+    let mut seen_info_fields = HashSet::new();
     let mut new_pois = Vec::new();
-    for poi in api_response.pois {
+    let mut changed_pois = Vec::new();
+    for poi in pois {
         match poi {
             Poi::Detailed(detailed_poi) => {
+                if !seen_backend_ids.insert(detailed_poi.backend.clone()) {
+                    continue;
+                }
+
+                if seen_types.insert(detailed_poi.poi_type.clone()) {
+                    let is_new_type = database.record_poi_type(&detailed_poi.poi_type, &detailed_poi.backend)?;
+                    if let LocationType::Unknown(code) = LocationType::from_code(&detailed_poi.poi_type) {
+                        println!(
+                            "Poi {} has the unknown type {}{}",
+                            detailed_poi.backend,
+                            code,
+                            if is_new_type { ", which was never seen before" } else { "" }
+                        );
+                    }
+                }
+
+                for (field, value) in &detailed_poi.info.unknown_fields {
+                    if seen_info_fields.insert(field.clone()) {
+                        println!("Poi {} has the unknown info field {}: {}", detailed_poi.backend, field, value);
+                    }
+                }
+
+                if !region.area.contains(detailed_poi.lat, detailed_poi.lng) {
+                    println!("Poi {} is outside of the route buffer.. skipped", detailed_poi.backend);
+                    continue;
+                }
+
+                if let Some(reason) = region.filter.rejects(&detailed_poi) {
+                    println!("Poi {} is filtered ({}).. skipped", detailed_poi.backend, reason);
+                    continue;
+                }
+
                 if let Some(known_poi) = known_pois.remove(&detailed_poi.backend) {
                     println!(
-                        "Found poi in database: {}, {}. Chat: {}: {}, {}",
-                        detailed_poi.id, detailed_poi.backend, known_poi.chat_id, known_poi.message_id_info, known_poi.message_id_location
+                        "Found poi in database: {}, {}. Messages: {:?}",
+                        detailed_poi.id, detailed_poi.backend, known_poi.message_ids
                     );
-                    continue;
 
+                    if known_poi.missed_runs > 0 {
+                        database.update_missed(&region.name, &known_poi.id, 0, None)?;
+                    }
+
+                    let changes = known_poi.changes(&detailed_poi);
+                    if !changes.is_empty() {
+                        changed_pois.push((known_poi, detailed_poi, changes));
+                    }
+                    continue;
                 }
 
                 new_pois.push(detailed_poi);
             }
             Poi::Cluster(cluster_poi) => {
-                println!("Found cluster poi.. but skipped: {:?}", cluster_poi)
+                println!("Found unresolved cluster poi.. skipped: {:?}", cluster_poi)
             }
         }
     }
 
-    for poi in new_pois.iter().clone() {
-        println!("Found new poi: {:?}.. sending telegram message", poi);
-        let info_message = telegram_bot.send_message(poi.to_telegram_message()).await;
+    let muted_until = database.get_muted_until(&region.chat_id)?.filter(|muted_until| *muted_until > Local::now().naive_local());
+    if let Some(muted_until) = muted_until {
+        println!("Chat {} is muted until {}.. {} new pois are announced later", region.chat_id, muted_until, new_pois.len());
+        new_pois.clear();
+    }
+
+    let mut outbox = Outbox::load(database, &region.name)?;
+
+    for poi in &new_pois {
+        let result = handle_new_poi(&region, &region_notifiers, database, &mut outbox, poi).await;
+        handle_failure(notifiers, failure_policy, &region, &poi.backend, result).await?;
+    }
+
+    for (known_poi, poi, changes) in &changed_pois {
+        let result = handle_changed_poi(&region, &region_notifiers, database, &mut outbox, known_poi, poi, changes).await;
+        handle_failure(notifiers, failure_policy, &region, &known_poi.backend_id, result).await?;
+    }
+
+    for known_poi in known_pois.values() {
+        let result = handle_missing_poi(&region, &region_notifiers, database, &mut outbox, grace_configuration, known_poi).await;
+        handle_failure(notifiers, failure_policy, &region, &known_poi.backend_id, result).await?;
+    }
+
+    outbox.reconcile(database, &region_notifiers).await
+}
+
+async fn handle_new_poi(
+    region: &Region,
+    region_notifiers: &[(String, Arc<dyn Notifier>)],
+    database: &mut dyn Storage,
+    outbox: &mut Outbox,
+    poi: &DetailedPoi,
+) -> Result<(), anyhow::Error> {
+    let inactive_poi = database.find_inactive_poi(&region.name, &poi.id, &poi.backend)?;
+
+    match &inactive_poi {
+        Some(inactive_poi) => {
+            println!("Found reappeared poi: {:?}, last seen {:?}.. sending notifications", poi, inactive_poi.last_seen)
+        }
+        None => println!("Found new poi: {:?}.. sending notifications", poi),
+    }
+
+    let event = if inactive_poi.is_some() { DeliveryEvent::Reappeared } else { DeliveryEvent::Appeared };
+    let mut message_ids = None;
+    for (name, notifier) in region_notifiers {
+        let send = notifier.send_new_poi(&region.name, poi, inactive_poi.is_some());
+        if let Some(sent_message_ids) = outbox.deliver(database, &poi.backend, event, name, "", send).await? {
+            message_ids = Some(sent_message_ids);
+        }
+    }
+
+    match &inactive_poi {
+        Some(inactive_poi) => database.reactivate_poi(&region.name, &inactive_poi.id, poi.clone(), message_ids.as_ref())?,
+        None => database.add_poi(&region.name, poi.clone(), message_ids.as_ref())?,
+    }
+    outbox.persist(database, &poi.backend)?;
+
+    match inactive_poi {
+        Some(inactive_poi) => {
+            let details = inactive_poi.last_seen.map(|last_seen| format!("last seen {last_seen}"));
+            database.add_event(&region.name, &poi.id, PoiEventType::Reappeared, details)?;
+        }
+        None => database.add_event(&region.name, &poi.id, PoiEventType::Appeared, None)?,
+    }
+
+    Ok(())
+}
+
+async fn handle_changed_poi(
+    region: &Region,
+    region_notifiers: &[(String, Arc<dyn Notifier>)],
+    database: &mut dyn Storage,
+    outbox: &mut Outbox,
+    known_poi: &KnownPoi,
+    poi: &DetailedPoi,
+    changes: &[PoiChange],
+) -> Result<(), anyhow::Error> {
+    let changes_text = changes.iter().map(|change| change.to_string()).collect::<Vec<_>>().join(", ");
+    println!("Poi {} changed ({}).. sending notifications", known_poi.backend_id, changes_text);
+
+    let mut message_ids = known_poi.message_ids.clone();
+    for (name, notifier) in region_notifiers {
+        let send = notifier.send_changed_poi(&region.name, known_poi, poi, changes);
+        let delivered = outbox.deliver(database, &known_poi.backend_id, DeliveryEvent::Changed, name, &changes_text, send).await?;
+        if let Some(sent_message_ids) = delivered {
+            message_ids = Some(sent_message_ids);
+        }
+    }
+
+    database.update_poi(&region.name, &known_poi.id, poi, message_ids.as_ref(), changes)?;
+    outbox.persist(database, &known_poi.backend_id)?;
+    for change in changes {
+        if let Some(event_type) = change.event_type() {
+            database.add_event(&region.name, &known_poi.id, event_type, Some(change.to_string()))?;
+        }
+    }
 
-        let latitude = poi.lat.parse::<f64>().expect("Failed to parse latitude");
-        let longitude = poi.lng.parse::<f64>().expect("Failed to parse longitude");
-        let location_message = telegram_bot.send_location(latitude, longitude).await;
+    Ok(())
+}
 
-        database
-            .add_poi(
-                poi.clone(),
-                info_message.chat.id,
-                info_message.id,
-                location_message.id,
-            );
+async fn handle_missing_poi(
+    region: &Region,
+    region_notifiers: &[(String, Arc<dyn Notifier>)],
+    database: &mut dyn Storage,
+    outbox: &mut Outbox,
+    grace_configuration: &GraceConfiguration,
+    known_poi: &KnownPoi,
+) -> Result<(), anyhow::Error> {
+    let now = Local::now().naive_local();
+    let missed_runs = known_poi.missed_runs + 1;
+    let first_missed_at = known_poi.first_missed_at.unwrap_or(now);
+    if !grace_configuration.is_gone(missed_runs, now - first_missed_at) {
+        println!("Poi {:?} is missing since {} runs.. keeping it for now", known_poi.backend_id, missed_runs);
+        database.update_missed(&region.name, &known_poi.id, missed_runs, Some(first_missed_at))?;
+        return Ok(());
     }
-    
-    for known_poi in known_pois.values().clone() {
-        println!("Poi {:?} is now inactive.. going to delete messages", known_poi.backend_id);
 
-        telegram_bot.delete_message(known_poi.chat_id, known_poi.message_id_info, known_poi.message_id_location).await;
-        database.update_last_seen(known_poi.id.clone());
+    println!("Poi {:?} is now inactive.. going to retract notifications", known_poi.backend_id);
+
+    // The poi stays active until every notifier retracted it, so a failed retraction is repeated in the next run
+    for (name, notifier) in region_notifiers {
+        let retract = async { notifier.retract_poi(&region.name, known_poi).await.map(|_| None) };
+        outbox.deliver(database, &known_poi.backend_id, DeliveryEvent::Disappeared, name, "", retract).await?;
     }
+    database.update_last_seen(&region.name, known_poi.id.clone())?;
+    outbox.persist(database, &known_poi.backend_id)?;
+    database.add_event(&region.name, &known_poi.id, PoiEventType::Disappeared, None)?;
 
     Ok(())
 }
+
+async fn handle_failure(
+    notifiers: &Notifiers,
+    failure_policy: FailurePolicy,
+    region: &Region,
+    backend_id: &str,
+    result: Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let Err(error) = result else {
+        return Ok(());
+    };
+
+    match failure_policy {
+        FailurePolicy::Abort => Err(anyhow!("Failed to handle poi {} in region {}: {}", backend_id, region.name, error)),
+        FailurePolicy::Skip => {
+            eprintln!("Failed to handle poi {} in region {}: {}.. retrying it in the next run", backend_id, region.name, error);
+            notifiers
+                .send_error(&format!(
+                    "Failed to handle poi {} in region {}, it is retried in the next run. {}",
+                    backend_id, region.name, error
+                ))
+                .await;
+            Ok(())
+        }
+    }
+}
@@ -1,37 +1,169 @@
+use std::sync::Arc;
 use std::time::Duration;
+use chrono::{Datelike, Local, Timelike, Weekday};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
 use tokio::time::sleep;
+use crate::blitzer_api_client::ApiClient;
+use crate::commands::SharedStorage;
+use crate::configuration::DaemonConfiguration;
+use crate::notifier::Notifiers;
 use crate::telegram::TelegramBot;
 
 mod blitzer_api_client;
+mod commands;
 mod configuration;
 mod database;
 mod handler;
+mod messages;
 mod model;
+mod notifier;
+mod outbox;
+mod route;
 mod telegram;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if arguments.first().is_some_and(|command| command == "migrate") {
+        return run_migrate(arguments.iter().any(|argument| argument == "--dry-run")).await;
+    }
+    if arguments.first().is_some_and(|command| command == "timeline") {
+        let Some(backend_id) = arguments.get(1) else {
+            anyhow::bail!("Usage: blitzer timeline <backend id>");
+        };
+        return run_timeline(backend_id).await;
+    }
+
     let telegram_bot = TelegramBot::try_new().await;
+    let notifiers = Notifiers::try_new(&telegram_bot).await?;
+    let api_client = ApiClient::from_configuration().await?;
+
+    println!("Init database connection...");
+    let database: SharedStorage = Arc::new(Mutex::new(database::try_new().await?));
+
+    if arguments.iter().any(|argument| argument == "--daemon") {
+        run_daemon(&telegram_bot, &notifiers, &api_client, &database).await?;
+    } else {
+        run_with_retries(&notifiers, &api_client, &database).await;
+    }
+
+    Ok(())
+}
+
+async fn run_migrate(dry_run: bool) -> Result<(), anyhow::Error> {
+    let mut database = database::open().await?;
+
+    if !dry_run {
+        return database::migrations::run(database.as_mut());
+    }
+
+    let pending_migrations = database::migrations::pending_migrations(database.as_mut())?;
+    println!("{} pending migrations", pending_migrations.len());
+    for migration in pending_migrations {
+        println!("-- Migration {}: {}", migration.version, migration.description);
+        for statement in migration.statements(&database.dialect()) {
+            println!("{};", statement);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_timeline(backend_id: &str) -> Result<(), anyhow::Error> {
+    let mut database = database::try_new().await?;
+
+    let timeline = database.get_timeline(backend_id)?;
+    println!("{} events for poi {}", timeline.len(), backend_id);
+    for event in timeline {
+        println!(
+            "{} [{}/{}] {} {}",
+            event.created_at,
+            event.region,
+            event.poi_id,
+            event.event_type,
+            event.details.unwrap_or_default()
+        );
+    }
 
+    Ok(())
+}
+
+async fn run_daemon(
+    telegram_bot: &TelegramBot,
+    notifiers: &Notifiers,
+    api_client: &ApiClient,
+    database: &SharedStorage,
+) -> Result<(), anyhow::Error> {
+    let daemon_configuration = configuration::get_daemon_configuration().await;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    println!("Start BlitzerNotifier in daemon mode");
+    let mut dispatcher = commands::dispatcher(telegram_bot.bot(), database.clone()).await;
+    let shutdown_token = dispatcher.shutdown_token();
+    let command_handler = tokio::spawn(async move { dispatcher.dispatch().await });
+
+    loop {
+        run_with_retries(notifiers, api_client, database).await;
+
+        let interval = current_interval(&daemon_configuration);
+        println!("Next run in {} seconds", interval.as_secs());
+
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+        }
+    }
+
+    println!("Received shutdown signal.. stopping BlitzerNotifier");
+    if let Ok(shutdown) = shutdown_token.shutdown() {
+        shutdown.await;
+    }
+    command_handler.await?;
+
+    Ok(())
+}
+
+fn current_interval(daemon_configuration: &DaemonConfiguration) -> Duration {
+    let now = Local::now();
+
+    let is_night = if daemon_configuration.night_start <= daemon_configuration.night_end {
+        (daemon_configuration.night_start..daemon_configuration.night_end).contains(&now.hour())
+    } else {
+        now.hour() >= daemon_configuration.night_start || now.hour() < daemon_configuration.night_end
+    };
+    let is_weekend = matches!(now.weekday(), Weekday::Sat | Weekday::Sun);
+
+    let interval = match (is_night, is_weekend) {
+        (true, _) if daemon_configuration.night_interval.is_some() => daemon_configuration.night_interval,
+        (_, true) => daemon_configuration.weekend_interval,
+        _ => None,
+    };
+
+    Duration::from_secs(interval.unwrap_or(daemon_configuration.interval))
+}
+
+async fn run_with_retries(notifiers: &Notifiers, api_client: &ApiClient, database: &SharedStorage) {
     let mut last_error = None;
     for try_run in 1..5 {
-        if let Err(error) = handler::handle(&telegram_bot).await {
+        if let Err(error) = handler::handle(notifiers, api_client, database.lock().await.as_mut()).await {
             eprintln!("Error: {} in try {}", error, try_run);
             last_error = Some(error);
         } else {
+            last_error = None;
             break;
         }
         sleep(Duration::from_secs(10)).await;
     }
 
     if let Some(error) = last_error {
-        telegram_bot
-            .send_message(format!(
+        notifiers
+            .send_error(&format!(
                 "Failed to execute bot... see log for more information. {}",
                 error
             ))
             .await;
     }
-    
-    Ok(())
 }
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use chrono::{Local, NaiveDateTime, NaiveTime};
+use crate::configuration;
+use crate::database::{KnownPoi, PoiChange};
+use crate::model::{Address, DetailedPoi, Language, LocationType};
+
+const ENGLISH_DETAILS: &str = "{{zip_code}} {{city}}{{#city_district}} ({{city_district}}){{/city_district}}: \n\n\
+    Address: {{street}}\nType: {{#icon}}{{icon}} {{/icon}}{{type}}\nMax speed: {{vmax}}{{#info}} \n\nAdditional info: {{info}}{{/info}} \n\
+    Created {{created}}, Confirmed: {{confirmed}}, BackendId: {{backend_id}}";
+
+const GERMAN_DETAILS: &str = "{{zip_code}} {{city}}{{#city_district}} ({{city_district}}){{/city_district}}: \n\n\
+    Adresse: {{street}}\nTyp: {{#icon}}{{icon}} {{/icon}}{{type}}\nHöchstgeschwindigkeit: {{vmax}}{{#info}} \n\nZusätzliche Infos: {{info}}{{/info}} \n\
+    Erstellt {{created}}, Bestätigt: {{confirmed}}, BackendId: {{backend_id}}";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextFormat {
+    Plain,
+    // Telegram html, the values are escaped and the speed limit is bold
+    Html,
+}
+
+pub enum Title {
+    NewPoi,
+    ReappearedPoi,
+    ChangedPoi,
+    GonePoi,
+    Error,
+}
+
+/// Renders the messages of the notifiers with the configured language and templates.
+pub struct Messages {
+    language: Language,
+    new_poi: String,
+    reappeared_poi: String,
+    changed_poi: String,
+    gone_poi: String,
+}
+
+impl Messages {
+    pub async fn from_configuration() -> Messages {
+        let message_configuration = configuration::get_message_configuration().await;
+
+        let (new_poi, reappeared_poi, changed_poi, gone_poi) = match message_configuration.language {
+            Language::English => (
+                format!("Attention: A new point of interest found at {ENGLISH_DETAILS}"),
+                format!("Attention: A known point of interest is back again at {ENGLISH_DETAILS}"),
+                format!("Attention: A known point of interest changed at {ENGLISH_DETAILS} \n\nUpdated: {{{{changes}}}}"),
+                String::from("The point of interest at {{street}}, {{zip_code}} {{city}} is gone. BackendId: {{backend_id}}"),
+            ),
+            Language::German => (
+                format!("Achtung: Neue Meldung in {GERMAN_DETAILS}"),
+                format!("Achtung: Eine bekannte Meldung ist wieder da in {GERMAN_DETAILS}"),
+                format!("Achtung: Geänderte Meldung in {GERMAN_DETAILS} \n\nAktualisiert: {{{{changes}}}}"),
+                String::from("Die Meldung in {{street}}, {{zip_code}} {{city}} ist nicht mehr aktiv. BackendId: {{backend_id}}"),
+            ),
+        };
+
+        Messages {
+            language: message_configuration.language,
+            new_poi: message_configuration.new_poi.unwrap_or(new_poi),
+            reappeared_poi: message_configuration.reappeared_poi.unwrap_or(reappeared_poi),
+            changed_poi: message_configuration.changed_poi.unwrap_or(changed_poi),
+            gone_poi: message_configuration.gone_poi.unwrap_or(gone_poi),
+        }
+    }
+
+    pub fn new_poi(&self, poi: &DetailedPoi, format: TextFormat) -> String {
+        with_links(&self.new_poi, &self.poi_values(poi, &[], format), poi, format)
+    }
+
+    pub fn reappeared_poi(&self, poi: &DetailedPoi, format: TextFormat) -> String {
+        with_links(&self.reappeared_poi, &self.poi_values(poi, &[], format), poi, format)
+    }
+
+    pub fn changed_poi(&self, poi: &DetailedPoi, changes: &[PoiChange], format: TextFormat) -> String {
+        with_links(&self.changed_poi, &self.poi_values(poi, changes, format), poi, format)
+    }
+
+    /// Title and address of a telegram venue.
+    pub fn venue(&self, poi: &DetailedPoi) -> (String, String) {
+        let vmax = match poi.vmax {
+            Some(vmax) => format!("{vmax} km/h"),
+            None => self.vmax(None),
+        };
+
+        (
+            format!("{} {} ({})", icon(&poi.poi_type), self.type_name(&poi.poi_type), vmax),
+            format!("{}, {} {}", poi.address.street, poi.address.zip_code, poi.address.city),
+        )
+    }
+
+    pub fn gone_poi(&self, known_poi: &KnownPoi) -> String {
+        let mut values = address_values(&known_poi.address);
+        values.extend(link_values(known_poi.lat, known_poi.lng));
+        values.extend([
+            ("backend_id", known_poi.backend_id.clone()),
+            ("type", self.type_name(&known_poi.poi_type)),
+            ("type_code", known_poi.poi_type.clone()),
+            ("category", self.category_name(&known_poi.poi_type)),
+            ("icon", icon(&known_poi.poi_type)),
+            ("vmax", self.vmax(known_poi.vmax)),
+            ("info", known_poi.info_desc.clone().unwrap_or_default()),
+            ("created", self.date(known_poi.create_date)),
+            ("confirmed", self.date(known_poi.confirm_date)),
+            ("lat", known_poi.lat.to_string()),
+            ("lng", known_poi.lng.to_string()),
+        ]);
+        render(&self.gone_poi, &values)
+    }
+
+    pub fn title(&self, title: Title, region: &str) -> String {
+        match (self.language, title) {
+            (Language::English, Title::NewPoi) => format!("New point of interest in {region}"),
+            (Language::English, Title::ReappearedPoi) => format!("Point of interest is back in {region}"),
+            (Language::English, Title::ChangedPoi) => format!("Point of interest changed in {region}"),
+            (Language::English, Title::GonePoi) => format!("Point of interest is gone in {region}"),
+            (Language::English, Title::Error) => String::from("BlitzerNotifier failed"),
+            (Language::German, Title::NewPoi) => format!("Neue Meldung in {region}"),
+            (Language::German, Title::ReappearedPoi) => format!("Meldung wieder da in {region}"),
+            (Language::German, Title::ChangedPoi) => format!("Meldung geändert in {region}"),
+            (Language::German, Title::GonePoi) => format!("Meldung nicht mehr aktiv in {region}"),
+            (Language::German, Title::Error) => String::from("BlitzerNotifier ist fehlgeschlagen"),
+        }
+    }
+
+    fn poi_values(
+        &self,
+        poi: &DetailedPoi,
+        changes: &[PoiChange],
+        format: TextFormat,
+    ) -> HashMap<&'static str, String> {
+        let mut values = address_values(&poi.address);
+        values.extend(link_values(poi.lat, poi.lng));
+        values.extend([
+            ("backend_id", poi.backend.clone()),
+            ("type", self.type_name(&poi.poi_type)),
+            ("type_code", poi.poi_type.clone()),
+            ("category", self.category_name(&poi.poi_type)),
+            ("icon", icon(&poi.poi_type)),
+            ("vmax", self.vmax(poi.vmax)),
+            ("info", poi.info.desc.clone().unwrap_or_default()),
+            ("created", self.date(poi.create_date)),
+            ("confirmed", self.date(poi.confirm_date)),
+            ("lat", poi.lat.to_string()),
+            ("lng", poi.lng.to_string()),
+            ("changes", changes.iter().map(|change| change.to_string()).collect::<Vec<_>>().join(", ")),
+        ]);
+
+        if format == TextFormat::Html {
+            for value in values.values_mut() {
+                *value = escape_html(value);
+            }
+            values.entry("vmax").and_modify(|vmax| *vmax = format!("<b>{vmax}</b>"));
+        }
+        values
+    }
+
+    fn type_name(&self, code: &str) -> String {
+        match LocationType::from_code(code) {
+            LocationType::Unknown(code) => code,
+            location_type => location_type.display_name(self.language).to_owned(),
+        }
+    }
+
+    fn category_name(&self, code: &str) -> String {
+        LocationType::from_code(code).metadata().category.display_name(self.language).to_owned()
+    }
+
+    fn vmax(&self, vmax: Option<i32>) -> String {
+        match (vmax, self.language) {
+            (Some(vmax), _) => vmax.to_string(),
+            (None, Language::English) => String::from("unknown"),
+            (None, Language::German) => String::from("unbekannt"),
+        }
+    }
+
+    fn date(&self, date: Option<NaiveDateTime>) -> String {
+        let Some(date) = date else {
+            return String::from(match self.language {
+                Language::English => "long long ago",
+                Language::German => "vor langer Zeit",
+            });
+        };
+        if date.date() == Local::now().date_naive() && date.time() != NaiveTime::MIN {
+            return match self.language {
+                Language::English => format!("today, {}", date.format("%H:%M")),
+                Language::German => format!("heute, {}", date.format("%H:%M")),
+            };
+        }
+
+        date.format("%d.%m.%Y").to_string()
+    }
+}
+
+fn icon(code: &str) -> String {
+    LocationType::from_code(code).metadata().icon.to_owned()
+}
+
+fn address_values(address: &Address) -> HashMap<&'static str, String> {
+    HashMap::from([
+        ("country", address.country.clone()),
+        ("state", address.state.clone()),
+        ("zip_code", address.zip_code.clone()),
+        ("city", address.city.clone()),
+        ("city_district", address.city_district.clone()),
+        ("street", address.street.clone()),
+    ])
+}
+
+fn link_values(lat: f64, lng: f64) -> [(&'static str, String); 3] {
+    [
+        ("osm_link", format!("https://www.openstreetmap.org/?mlat={lat}&mlon={lng}#map=17/{lat}/{lng}")),
+        ("google_maps_link", format!("https://www.google.com/maps/search/?api=1&query={lat},{lng}")),
+        ("waze_link", format!("https://waze.com/ul?ll={lat},{lng}&navigate=yes")),
+    ]
+}
+
+// A html message replaces the location message, so it links to the maps instead. Templates which place the links
+// themselves get none appended.
+fn with_links(template: &str, values: &HashMap<&str, String>, poi: &DetailedPoi, format: TextFormat) -> String {
+    let message = render(template, values);
+    if format == TextFormat::Plain || has_links(template) {
+        return message;
+    }
+
+    let links = link_values(poi.lat, poi.lng)
+        .iter()
+        .zip(["OpenStreetMap", "Google Maps", "Waze"])
+        .map(|((_, url), name)| format!("<a href=\"{}\">{}</a>", escape_html(url), name))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    format!("{message}\n\n{links}")
+}
+
+fn has_links(template: &str) -> bool {
+    link_values(0.0, 0.0)
+        .iter()
+        .any(|(name, _)| template.contains(&format!("{{{{{name}}}}}")))
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Replaces `{{name}}` with the value and keeps `{{#name}}...{{/name}}` only if the value is not empty.
+fn render(template: &str, values: &HashMap<&str, String>) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let tag = &rest[2..end];
+        rest = &rest[end + 2..];
+
+        if let Some(name) = tag.strip_prefix('#') {
+            let closing_tag = format!("{{{{/{name}}}}}");
+            let (section, after_section) = rest.split_once(&closing_tag).unwrap_or((rest, ""));
+            if values.get(name).is_some_and(|value| !value.is_empty()) {
+                rendered.push_str(&render(section, values));
+            }
+            rest = after_section;
+            continue;
+        }
+
+        match values.get(tag) {
+            Some(value) => rendered.push_str(value),
+            None => rendered.push_str(&format!("{{{{{tag}}}}}")),
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("city", String::from("Hamburg")),
+            ("vmax", String::from("50")),
+            ("info", String::new()),
+        ])
+    }
+
+    #[test]
+    fn renders_placeholders_and_sections() {
+        assert_eq!(render("{{city}}: {{vmax}} km/h", &values()), "Hamburg: 50 km/h");
+        assert_eq!(render("{{#vmax}}Max {{vmax}}{{/vmax}}{{#info}} Info {{info}}{{/info}}!", &values()), "Max 50!");
+        assert_eq!(render("{{#missing}}hidden{{/missing}}shown", &values()), "shown");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders() {
+        assert_eq!(render("{{city}} {{unknown}}", &values()), "Hamburg {{unknown}}");
+    }
+
+    #[test]
+    fn keeps_malformed_braces() {
+        assert_eq!(render("{{city}} {{vmax", &values()), "Hamburg {{vmax");
+        assert_eq!(render("{city} }}{{city}}", &values()), "{city} }}Hamburg");
+        assert_eq!(render("{{#vmax}}Max {{vmax}}", &values()), "Max 50");
+        assert_eq!(render("{{}}", &values()), "{{}}");
+    }
+
+    #[test]
+    fn detects_templates_with_links() {
+        assert!(has_links("{{city}} {{osm_link}}"));
+        assert!(has_links("<a href=\"{{waze_link}}\">Waze</a>"));
+        assert!(!has_links("{{city}} osm_link"));
+    }
+}
@@ -1,10 +1,10 @@
-use std::collections::HashMap;
-use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_variant::to_variant_name;
-use std::fmt::Display;
 use strum_macros::EnumIter;
 
-#[derive(Serialize, Deserialize, EnumIter, Debug)]
+#[derive(Serialize, Deserialize, EnumIter, Debug, Clone)]
 pub enum LocationType {
     #[serde(rename = "0")]
     BlitzerMobile0,
@@ -84,36 +84,142 @@ pub enum LocationType {
     PolylineTraffic,
     #[serde(rename = "pics")]
     BlitzerBilder,
+    // Codes which were added to the api later on
+    #[serde(untagged)]
+    #[strum(disabled)]
+    Unknown(String),
 }
 impl LocationType {
+    /// Parses the type code of the api, e.g. "103" or "ts".
+    pub fn from_code(code: &str) -> LocationType {
+        LocationType::deserialize(serde_json::Value::from(code))
+            .unwrap_or_else(|_| LocationType::Unknown(code.to_owned()))
+    }
+
+    pub fn code(&self) -> String {
+        match self {
+            LocationType::Unknown(code) => code.clone(),
+            location_type => to_variant_name(location_type).expect("Should be rename enum value").to_owned(),
+        }
+    }
+
+    /// Category, names, icon and whether the type is requested if a region has no `types`.
+    pub fn metadata(&self) -> LocationTypeMetadata {
+        use Category::*;
+
+        let (category, english, german, icon, is_default) = match self {
+            LocationType::BlitzerMobile0 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile1 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile2 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile3 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile4 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile5 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::BlitzerMobile6 => (Mobile, "Mobile speed camera", "Mobiler Blitzer", "🚓", true),
+            LocationType::Stauende => (Hazard, "End of traffic jam", "Stauende", "🚗", false),
+            LocationType::Gefahrenstelle21 => (Hazard, "Danger spot", "Gefahrenstelle", "⚠️", false),
+            LocationType::Baustelle22 => (Construction, "Roadworks", "Baustelle", "🚧", false),
+            LocationType::Gefahrenstelle23 => (Hazard, "Danger spot", "Gefahrenstelle", "⚠️", false),
+            LocationType::Gefahrenstelle24 => (Hazard, "Danger spot", "Gefahrenstelle", "⚠️", false),
+            LocationType::Gefahrenstelle25 => (Hazard, "Danger spot", "Gefahrenstelle", "⚠️", false),
+            LocationType::Baustelle26 => (Construction, "Roadworks", "Baustelle", "🚧", false),
+            LocationType::Gefahrenstelle29 => (Hazard, "Danger spot", "Gefahrenstelle", "⚠️", false),
+            LocationType::Blitzer101 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer102 => (TrafficLight, "Red light camera", "Rotlichtblitzer", "🚦", true),
+            LocationType::Blitzer103 => (TrafficLight, "Red light and speed camera", "Rotlicht- und Geschwindigkeitsblitzer", "🚦", true),
+            LocationType::Blitzer104 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer105 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer106 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer107 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer108 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer109 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer110 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer111 => (SectionControl, "Section control", "Abschnittskontrolle", "🛣️", true),
+            LocationType::Blitzer112 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer113 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Tunnel => (Fixed, "Tunnel speed camera", "Tunnelblitzer", "🚇", true),
+            LocationType::Blitzer115 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Blitzer117 => (Fixed, "Fixed speed camera", "Fester Blitzer", "📸", true),
+            LocationType::Kulturguide1015 => (Information, "Culture guide", "Kulturguide", "🏛️", false),
+            LocationType::Kulturguide1016 => (Information, "Culture guide", "Kulturguide", "🏛️", false),
+            LocationType::Hotspot2015 => (Information, "Hotspot", "Hotspot", "📍", false),
+            LocationType::BlitzerTeilstat => (Mobile, "Semi-stationary speed camera", "Teilstationärer Blitzer", "🚐", true),
+            LocationType::Polizeimeldung1 => (Hazard, "Police report", "Polizeimeldung", "👮", false),
+            LocationType::Polizeimeldung2 => (Hazard, "Police report", "Polizeimeldung", "👮", false),
+            LocationType::PolylineTraffic => (Information, "Traffic", "Verkehr", "🚥", false),
+            LocationType::BlitzerBilder => (Information, "Speed camera picture", "Blitzerbild", "🖼️", false),
+            LocationType::Unknown(_) => (Unknown, "Unknown type", "Unbekannter Typ", "❓", false),
+        };
+
+        LocationTypeMetadata {
+            category,
+            english,
+            german,
+            icon,
+            is_default,
+        }
+    }
+
     pub fn is_default(&self) -> bool {
-        matches!(self, |LocationType::Blitzer101
-            | LocationType::Blitzer102
-            | LocationType::Blitzer103
-            | LocationType::Blitzer104
-            | LocationType::Blitzer105
-            | LocationType::Blitzer106
-            | LocationType::Blitzer107
-            | LocationType::Blitzer108
-            | LocationType::Blitzer109
-            | LocationType::Blitzer110
-            | LocationType::Blitzer111
-            | LocationType::Blitzer112
-            | LocationType::Blitzer113
-            | LocationType::Tunnel
-            | LocationType::Blitzer115
-            | LocationType::Blitzer117
-            | LocationType::BlitzerTeilstat
-            | LocationType::BlitzerMobile0
-            | LocationType::BlitzerMobile1
-            | LocationType::BlitzerMobile2
-            | LocationType::BlitzerMobile3
-            | LocationType::BlitzerMobile4
-            | LocationType::BlitzerMobile5
-            | LocationType::BlitzerMobile6)
+        self.metadata().is_default
+    }
+
+    /// Human readable name of the type, as shown in the messages.
+    pub fn display_name(&self, language: Language) -> &'static str {
+        let metadata = self.metadata();
+        match language {
+            Language::English => metadata.english,
+            Language::German => metadata.german,
+        }
     }
 }
 
+pub struct LocationTypeMetadata {
+    pub category: Category,
+    pub english: &'static str,
+    pub german: &'static str,
+    pub icon: &'static str,
+    pub is_default: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Fixed,
+    Mobile,
+    SectionControl,
+    TrafficLight,
+    Hazard,
+    Construction,
+    Information,
+    Unknown,
+}
+
+impl Category {
+    pub fn display_name(&self, language: Language) -> &'static str {
+        let (english, german) = match self {
+            Category::Fixed => ("Fixed speed camera", "Stationärer Blitzer"),
+            Category::Mobile => ("Mobile speed camera", "Mobiler Blitzer"),
+            Category::SectionControl => ("Section control", "Abschnittskontrolle"),
+            Category::TrafficLight => ("Traffic light camera", "Ampelblitzer"),
+            Category::Hazard => ("Hazard", "Gefahr"),
+            Category::Construction => ("Construction", "Baustelle"),
+            Category::Information => ("Information", "Information"),
+            Category::Unknown => ("Unknown", "Unbekannt"),
+        };
+
+        match language {
+            Language::English => english,
+            Language::German => german,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Language {
+    English,
+    German,
+}
+
 pub struct BlitzerClientRequestParams {
     pub zoom_level: i32,
     pub types: Vec<LocationType>,
@@ -128,7 +234,7 @@ impl BlitzerClientRequestParams {
                 String::from("type"),
                 self.types
                     .iter()
-                    .map(|t| to_variant_name(t).expect("Should be rename enum value"))
+                    .map(|t| t.code())
                     .collect::<Vec<_>>()
                     .join(","),
             ),
@@ -137,6 +243,13 @@ impl BlitzerClientRequestParams {
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct Coordinate {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+#[derive(Clone)]
 pub struct LocationBox {
     pub lat_min: f64,
     pub lng_min: f64,
@@ -144,7 +257,65 @@ pub struct LocationBox {
     pub lng_max: f64,
 }
 
-impl Display for LocationBox {
+// Length of a degree of latitude
+const KILOMETERS_PER_DEGREE: f64 = 111.32;
+// Bounds the requests of a single box, however small the configured tile size is
+const MAX_TILES_PER_SIDE: usize = 20;
+
+impl LocationBox {
+    /// Splits the box into a grid of tiles which are at most `max_size` km high and wide. The grid has at most
+    /// `MAX_TILES_PER_SIDE` rows and columns, larger boxes get larger tiles.
+    pub fn tiles(&self, max_size: f64) -> Vec<LocationBox> {
+        let center_lat = ((self.lat_min + self.lat_max) / 2.0).to_radians();
+        let height = (self.lat_max - self.lat_min).abs() * KILOMETERS_PER_DEGREE;
+        let width = (self.lng_max - self.lng_min).abs() * KILOMETERS_PER_DEGREE * center_lat.cos();
+        let rows = (height / max_size).ceil().max(1.0);
+        let columns = (width / max_size).ceil().max(1.0);
+        if rows > MAX_TILES_PER_SIDE as f64 || columns > MAX_TILES_PER_SIDE as f64 {
+            println!(
+                "LocationBox {} needs {}x{} tiles of {} km.. limited to {} tiles per side",
+                self, rows, columns, max_size, MAX_TILES_PER_SIDE
+            );
+        }
+        let rows = rows.min(MAX_TILES_PER_SIDE as f64) as usize;
+        let columns = columns.min(MAX_TILES_PER_SIDE as f64) as usize;
+        let lat_step = (self.lat_max - self.lat_min) / rows as f64;
+        let lng_step = (self.lng_max - self.lng_min) / columns as f64;
+
+        (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| LocationBox {
+                    lat_min: self.lat_min + lat_step * row as f64,
+                    lng_min: self.lng_min + lng_step * column as f64,
+                    lat_max: self.lat_min + lat_step * (row + 1) as f64,
+                    lng_max: self.lng_min + lng_step * (column + 1) as f64,
+                })
+            })
+            .collect()
+    }
+
+    /// Splits the box into four equal boxes, in the order south west, south east, north west, north east.
+    pub fn quarters(&self) -> [LocationBox; 4] {
+        let lat_center = (self.lat_min + self.lat_max) / 2.0;
+        let lng_center = (self.lng_min + self.lng_max) / 2.0;
+
+        [
+            LocationBox { lat_min: self.lat_min, lng_min: self.lng_min, lat_max: lat_center, lng_max: lng_center },
+            LocationBox { lat_min: self.lat_min, lng_min: lng_center, lat_max: lat_center, lng_max: self.lng_max },
+            LocationBox { lat_min: lat_center, lng_min: self.lng_min, lat_max: self.lat_max, lng_max: lng_center },
+            LocationBox { lat_min: lat_center, lng_min: lng_center, lat_max: self.lat_max, lng_max: self.lng_max },
+        ]
+    }
+
+    /// The index of the quarter which contains the coordinate. Coordinates outside of the box belong to the nearest quarter.
+    pub fn quarter_index(&self, lat: f64, lng: f64) -> usize {
+        let is_north = lat >= (self.lat_min + self.lat_max) / 2.0;
+        let is_east = lng >= (self.lng_min + self.lng_max) / 2.0;
+        usize::from(is_north) * 2 + usize::from(is_east)
+    }
+}
+
+impl std::fmt::Display for LocationBox {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
@@ -158,10 +329,51 @@ impl Display for LocationBox {
 #[derive(Debug, Deserialize)]
 pub struct ApiResponse {
     pub pois: Vec<Poi>,
-    // pub grid: Vec<Grid>,
-    // pub infos: Vec<Info>,
+    #[serde(default, deserialize_with = "deserialize_lenient_list")]
+    pub grid: Vec<GridCell>,
+    #[serde(default, deserialize_with = "deserialize_lenient_list")]
+    pub infos: Vec<ResponseInfo>,
+}
+
+/// A cell of the grid which the api uses to cluster the pois, with the position and counter like a cluster poi.
+/// The grid is not documented, so every field is optional and the others are kept in `unknown_fields`.
+#[derive(Debug, Deserialize)]
+pub struct GridCell {
+    #[serde(default, deserialize_with = "deserialize_optional_coordinate")]
+    pub lat: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_optional_coordinate")]
+    pub lng: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub counter: Option<String>,
+    #[serde(flatten)]
+    pub unknown_fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl std::fmt::Display for GridCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.lat, self.lng) {
+            (Some(lat), Some(lng)) => write!(f, "{},{}", lat, lng)?,
+            _ => write!(f, "without position")?,
+        }
+        write!(f, " with {} pois", self.counter.as_deref().unwrap_or("unknown"))?;
+        if !self.unknown_fields.is_empty() {
+            write!(f, " {}", serde_json::Value::from_iter(self.unknown_fields.clone()))?;
+        }
+        Ok(())
+    }
 }
 
+/// A message of the api for the requested area. It is modeled apart from the `Info` of a poi, since nothing tells
+/// that both have the same fields, only the text in `desc` is known and the others are kept in `unknown_fields`.
+#[derive(Debug, Deserialize)]
+pub struct ResponseInfo {
+    #[serde(default, deserialize_with = "deserialize_optional_text")]
+    pub desc: Option<String>,
+    #[serde(flatten)]
+    pub unknown_fields: BTreeMap<String, serde_json::Value>,
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Deserialize)]
 #[serde(untagged)] // Allow POIs to take on different forms without explicit tags in the JSON
 pub enum Poi {
@@ -172,16 +384,21 @@ pub enum Poi {
 #[derive(Debug, Deserialize, Clone)]
 pub struct DetailedPoi {
     pub id: String,
-    pub lat: String,
-    pub lng: String,
+    #[serde(deserialize_with = "deserialize_coordinate")]
+    pub lat: f64,
+    #[serde(deserialize_with = "deserialize_coordinate")]
+    pub lng: f64,
     pub address: Address,
     pub content: String,
     pub backend: String,
     #[serde(rename = "type")]
     pub poi_type: String,
-    pub vmax: String,
-    pub create_date: String,
-    pub confirm_date: String,
+    #[serde(deserialize_with = "deserialize_vmax")]
+    pub vmax: Option<i32>,
+    #[serde(deserialize_with = "deserialize_api_date")]
+    pub create_date: Option<NaiveDateTime>,
+    #[serde(deserialize_with = "deserialize_api_date")]
+    pub confirm_date: Option<NaiveDateTime>,
     pub info: Info,
 }
 
@@ -196,7 +413,7 @@ pub struct ClusterPoi {
     pub cluster_type: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Address {
     pub country: String,
     pub state: String,
@@ -206,58 +423,222 @@ pub struct Address {
     pub street: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Additional information of a poi. Fields which are not modeled yet are kept in `unknown_fields`, so new fields
+/// of the api show up in the log and the database.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Info {
     pub desc: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub quality: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub confirmed: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub fixed: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub length: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_text", skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    #[serde(flatten)]
+    pub unknown_fields: BTreeMap<String, serde_json::Value>,
 }
 
-impl DetailedPoi {
-    pub fn to_telegram_message(&self) -> String {
-        let cloned_poi = self.to_owned();
+impl Info {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Info should be serializable")
+    }
+}
 
-        let mut city = format!(
-            "{} {}",
-            cloned_poi.address.zip_code, cloned_poi.address.city
-        );
-        if !&cloned_poi.address.city_district.is_empty() {
-            city = format!("{city} ({})", cloned_poi.address.city_district);
-        }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    String(String),
+    Number(f64),
+}
 
-        let poi_type: LocationType = serde_json::from_str(&format!("\"{}\"", cloned_poi.poi_type))
-            .expect("Invalid value for LocationType");
+fn deserialize_coordinate<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(value) => value.trim().parse().map_err(serde::de::Error::custom),
+        StringOrNumber::Number(value) => Ok(value),
+    }
+}
 
-        let mut base_message = format!("Attention: A new point of interest found at {city}: \n\nAddress: {}\nType: {:?}\nMax speed: {}",
-                                       cloned_poi.address.street,
-                                       poi_type,
-                                       cloned_poi.vmax
-        );
+fn deserialize_optional_coordinate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<StringOrNumber>::deserialize(deserializer)? {
+        Some(StringOrNumber::String(value)) => Ok(value.trim().parse().ok()),
+        Some(StringOrNumber::Number(value)) => Ok(Some(value)),
+        None => Ok(None),
+    }
+}
 
-        if cloned_poi.info.desc.is_some() {
-            base_message = format!(
-                "{} \n\nAdditional info: {}",
-                base_message,
-                cloned_poi.info.desc.unwrap()
-            );
-        }
-        base_message = format!(
-            "{} \nCreated {}, Confirmed: {}, BackendId: {}",
-            base_message,
-            format_date(cloned_poi.create_date),
-            format_date(cloned_poi.confirm_date),
-            cloned_poi.backend
-        );
+// The api is not consistent in its types, so strings, numbers and booleans are all kept as text
+fn deserialize_optional_text<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(value) => Some(value),
+        value => Some(value.to_string()),
+    })
+}
+
+// The grid and infos are only logged, so an unexpected shape must not fail the whole response
+fn deserialize_lenient_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    let values = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Array(values) => values,
+        serde_json::Value::Null => Vec::new(),
+        value => vec![value],
+    };
+
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value(value.clone()) {
+            Ok(element) => Some(element),
+            Err(error) => {
+                println!("Ignoring unexpected element of the api response {}: {}", value, error);
+                None
+            }
+        })
+        .collect())
+}
+
+// The api sends an empty string or 0 if there is no speed limit
+fn deserialize_vmax<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let vmax = match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(value) => value.trim().parse::<i32>().ok(),
+        StringOrNumber::Number(value) => Some(value as i32),
+    };
 
-        base_message
+    Ok(vmax.filter(|vmax| *vmax > 0))
+}
+
+// The api sends either a date ("24.12.2024"), only a time for today ("13:37")
+// or "01.01.1970" if the date is unknown
+fn deserialize_api_date<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = String::deserialize(deserializer)?;
+    Ok(parse_api_date(date.trim()))
+}
+
+fn parse_api_date(date: &str) -> Option<NaiveDateTime> {
+    if date.is_empty() || date == "01.01.1970" {
+        return None;
+    }
+
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(date, "%d.%m.%Y %H:%M") {
+        return Some(date_time);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(date, "%d.%m.%Y") {
+        return Some(date.and_time(NaiveTime::MIN));
     }
+    if let Ok(time) = NaiveTime::parse_from_str(date, "%H:%M") {
+        return Some(Local::now().date_naive().and_time(time));
+    }
+
+    println!("Unknown date format: {}", date);
+    None
 }
 
-fn format_date(date: String) -> String {
-    if date.eq("01.01.1970") {
-        return String::from("long long ago");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date_time(date: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap())
     }
-    if date.contains(".") {
-        return date;
+
+    #[test]
+    fn parses_api_dates() {
+        assert_eq!(parse_api_date("24.12.2024"), date_time("2024-12-24 00:00"));
+        assert_eq!(parse_api_date("24.12.2024 13:37"), date_time("2024-12-24 13:37"));
+        assert_eq!(
+            parse_api_date("13:37"),
+            Some(Local::now().date_naive().and_time(NaiveTime::from_hms_opt(13, 37, 0).unwrap()))
+        );
     }
 
-    format!("today, {}", date)
+    #[test]
+    fn ignores_unknown_api_dates() {
+        assert_eq!(parse_api_date(""), None);
+        assert_eq!(parse_api_date("01.01.1970"), None);
+        assert_eq!(parse_api_date("yesterday"), None);
+        assert_eq!(parse_api_date("31.02.2024"), None);
+    }
+
+    #[test]
+    fn parses_grid_and_infos() {
+        let response: ApiResponse = serde_json::from_value(json!({
+            "pois": [],
+            "grid": [{"lat": "53.5", "lng": 9.9, "counter": 12, "size": 2}],
+            "infos": [{"desc": "Test", "new_field": true}],
+        }))
+        .unwrap();
+
+        assert_eq!(response.grid[0].lat, Some(53.5));
+        assert_eq!(response.grid[0].lng, Some(9.9));
+        assert_eq!(response.grid[0].counter.as_deref(), Some("12"));
+        assert_eq!(response.grid[0].unknown_fields.get("size"), Some(&json!(2)));
+        assert_eq!(response.grid[0].to_string(), "53.5,9.9 with 12 pois {\"size\":2}");
+        assert_eq!(response.infos[0].desc.as_deref(), Some("Test"));
+        assert_eq!(response.infos[0].unknown_fields.get("new_field"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn parses_unexpected_grid_and_infos_leniently() {
+        let response: ApiResponse = serde_json::from_value(json!({
+            "pois": [],
+            "grid": [{"lat": "unknown"}, 5],
+            "infos": [{"desc": 5}, "text"],
+        }))
+        .unwrap();
+
+        assert_eq!(response.grid.len(), 1);
+        assert_eq!(response.grid[0].lat, None);
+        assert_eq!(response.grid[0].to_string(), "without position with unknown pois");
+        assert_eq!(response.infos.len(), 1);
+        assert_eq!(response.infos[0].desc.as_deref(), Some("5"));
+
+        let response: ApiResponse = serde_json::from_value(json!({"pois": [], "grid": null, "infos": {"desc": "Test"}})).unwrap();
+        assert!(response.grid.is_empty());
+        assert_eq!(response.infos.len(), 1);
+    }
+
+    #[test]
+    fn splits_boxes_into_tiles() {
+        let location_box = LocationBox { lat_min: 50.0, lng_min: 8.0, lat_max: 51.0, lng_max: 9.0 };
+
+        assert_eq!(location_box.tiles(200.0).len(), 1);
+        assert_eq!(location_box.tiles(50.0).len(), 3 * 2);
+        assert_eq!(location_box.tiles(0.001).len(), MAX_TILES_PER_SIDE * MAX_TILES_PER_SIDE);
+    }
+
+    #[test]
+    fn deserializes_vmax() {
+        assert_eq!(deserialize_vmax(json!("50")).unwrap(), Some(50));
+        assert_eq!(deserialize_vmax(json!(" 30 ")).unwrap(), Some(30));
+        assert_eq!(deserialize_vmax(json!(80)).unwrap(), Some(80));
+        assert_eq!(deserialize_vmax(json!("")).unwrap(), None);
+        assert_eq!(deserialize_vmax(json!("0")).unwrap(), None);
+        assert_eq!(deserialize_vmax(json!(0)).unwrap(), None);
+        assert_eq!(deserialize_vmax(json!("unknown")).unwrap(), None);
+        assert!(deserialize_vmax(json!(null)).is_err());
+    }
 }
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use async_trait::async_trait;
+use teloxide::Bot;
+use crate::configuration;
+use crate::configuration::{NotifierConfiguration, Region, TelegramFormat};
+use crate::database::{KnownPoi, MessageIds, PoiChange};
+use crate::messages::{Messages, TextFormat, Title};
+use crate::model::DetailedPoi;
+use crate::notifier::email_notifier::EmailNotifier;
+use crate::notifier::matrix_notifier::MatrixNotifier;
+use crate::notifier::push_notifier::{GotifyNotifier, NtfyNotifier};
+use crate::notifier::telegram_notifier::TelegramNotifier;
+use crate::notifier::webhook_notifier::WebhookNotifier;
+use crate::telegram::TelegramBot;
+
+mod email_notifier;
+mod matrix_notifier;
+mod push_notifier;
+mod telegram_notifier;
+mod webhook_notifier;
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Announces a new or reappeared poi. Only notifiers which edit their messages later on return message ids.
+    async fn send_new_poi(&self, region: &str, poi: &DetailedPoi, reappeared: bool) -> anyhow::Result<Option<MessageIds>>;
+
+    async fn send_changed_poi(
+        &self,
+        region: &str,
+        known_poi: &KnownPoi,
+        poi: &DetailedPoi,
+        changes: &[PoiChange],
+    ) -> anyhow::Result<Option<MessageIds>>;
+
+    async fn retract_poi(&self, region: &str, known_poi: &KnownPoi) -> anyhow::Result<()>;
+
+    async fn send_error(&self, message: &str) -> anyhow::Result<()>;
+
+    /// Removes the messages of a notification whose poi was never stored, so they are not left behind.
+    async fn discard(&self, _message_ids: &MessageIds) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// A message which could not be delivered, even after retrying. It is kept as dead letter
+/// instead of failing the whole run.
+#[derive(Debug)]
+pub struct FailedDelivery {
+    pub notifier: String,
+    pub url: String,
+    pub payload: String,
+    pub error: String,
+}
+
+impl Display for FailedDelivery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to deliver message of notifier {} to {}: {}", self.notifier, self.url, self.error)
+    }
+}
+
+impl std::error::Error for FailedDelivery {}
+
+/// Notifiers which can only send a text, but can not change it later on.
+#[async_trait]
+trait TextNotifier: Send + Sync {
+    fn messages(&self) -> &Messages;
+
+    async fn send_text(&self, title: &str, text: &str) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl<T: TextNotifier> Notifier for T {
+    async fn send_new_poi(&self, region: &str, poi: &DetailedPoi, reappeared: bool) -> anyhow::Result<Option<MessageIds>> {
+        let messages = self.messages();
+        if reappeared {
+            self.send_text(&messages.title(Title::ReappearedPoi, region), &messages.reappeared_poi(poi, TextFormat::Plain)).await?;
+        } else {
+            self.send_text(&messages.title(Title::NewPoi, region), &messages.new_poi(poi, TextFormat::Plain)).await?;
+        }
+        Ok(None)
+    }
+
+    async fn send_changed_poi(
+        &self,
+        region: &str,
+        _known_poi: &KnownPoi,
+        poi: &DetailedPoi,
+        changes: &[PoiChange],
+    ) -> anyhow::Result<Option<MessageIds>> {
+        let messages = self.messages();
+        self.send_text(&messages.title(Title::ChangedPoi, region), &messages.changed_poi(poi, changes, TextFormat::Plain)).await?;
+        Ok(None)
+    }
+
+    async fn retract_poi(&self, region: &str, known_poi: &KnownPoi) -> anyhow::Result<()> {
+        let messages = self.messages();
+        self.send_text(&messages.title(Title::GonePoi, region), &messages.gone_poi(known_poi)).await
+    }
+
+    async fn send_error(&self, message: &str) -> anyhow::Result<()> {
+        self.send_text(&self.messages().title(Title::Error, ""), message).await
+    }
+}
+
+enum ConfiguredNotifier {
+    // Telegram notifiers are created per region, as every region can have its own chat
+    Telegram,
+    Shared(Arc<dyn Notifier>),
+}
+
+/// All notifiers of the settings, created once and shared by the regions.
+pub struct Notifiers {
+    bot: Bot,
+    messages: Arc<Messages>,
+    telegram_format: TelegramFormat,
+    default_chat_id: String,
+    notifiers: HashMap<String, ConfiguredNotifier>,
+    error_notifiers: Vec<String>,
+}
+
+impl Notifiers {
+    pub async fn try_new(telegram_bot: &TelegramBot) -> anyhow::Result<Notifiers> {
+        let messages = Arc::new(Messages::from_configuration().await);
+        let mut notifiers = HashMap::new();
+        for (name, notifier_configuration) in configuration::get_notifier_configurations().await {
+            let notifier: Arc<dyn Notifier> = match notifier_configuration {
+                NotifierConfiguration::Telegram {} => {
+                    notifiers.insert(name, ConfiguredNotifier::Telegram);
+                    continue;
+                }
+                NotifierConfiguration::Matrix { homeserver, access_token, room_id } => {
+                    Arc::new(MatrixNotifier::new(messages.clone(), homeserver, access_token, room_id))
+                }
+                NotifierConfiguration::Ntfy { url, token } => Arc::new(NtfyNotifier::new(messages.clone(), url, token)),
+                NotifierConfiguration::Gotify { url, token, priority } => {
+                    Arc::new(GotifyNotifier::new(messages.clone(), url, token, priority))
+                }
+                NotifierConfiguration::Webhook { url, headers, secret, retries } => Arc::new(WebhookNotifier::try_new(
+                    name.clone(),
+                    url,
+                    headers.unwrap_or_default(),
+                    secret,
+                    retries,
+                )?),
+                NotifierConfiguration::Email { host, port, username, password, from, to } => {
+                    Arc::new(EmailNotifier::try_new(messages.clone(), &host, port, username, password, &from, &to)?)
+                }
+            };
+            notifiers.insert(name, ConfiguredNotifier::Shared(notifier));
+        }
+
+        // An unknown notifier would otherwise only fail in the middle of a run, after others already sent
+        let error_notifiers = configuration::get_error_notifiers().await;
+        for name in &error_notifiers {
+            if !notifiers.contains_key(name) {
+                anyhow::bail!("Configuration should have a notifier named {} for error_notifiers", name);
+            }
+        }
+        for region in configuration::get_regions().await {
+            if let Some(name) = region.notifiers.iter().find(|name| !notifiers.contains_key(*name)) {
+                anyhow::bail!("Configuration should have a notifier named {} for region {}", name, region.name);
+            }
+        }
+
+        Ok(Notifiers {
+            bot: telegram_bot.bot(),
+            messages,
+            telegram_format: configuration::get_telegram_format().await,
+            default_chat_id: telegram_bot.chat_id().to_owned(),
+            notifiers,
+            error_notifiers,
+        })
+    }
+
+    /// The notifiers of a region with their names.
+    pub fn for_region(&self, region: &Region) -> Vec<(String, Arc<dyn Notifier>)> {
+        region
+            .notifiers
+            .iter()
+            .map(|name| (name.clone(), self.notifier(name, &region.chat_id)))
+            .collect()
+    }
+
+    pub async fn send_error(&self, message: &str) {
+        for name in &self.error_notifiers {
+            if let Err(error) = self.notifier(name, &self.default_chat_id).send_error(message).await {
+                eprintln!("Failed to send error to notifier {}: {}", name, error);
+            }
+        }
+    }
+
+    fn notifier(&self, name: &str, chat_id: &str) -> Arc<dyn Notifier> {
+        match self.notifiers.get(name) {
+            Some(ConfiguredNotifier::Telegram) => Arc::new(TelegramNotifier::new(
+                self.bot.clone(),
+                self.messages.clone(),
+                self.telegram_format,
+                chat_id.to_owned(),
+            )),
+            Some(ConfiguredNotifier::Shared(notifier)) => notifier.clone(),
+            None => unreachable!("Notifier {name} should be checked when the notifiers are created"),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use crate::messages::Messages;
+use crate::notifier::TextNotifier;
+
+/// Sends plain text mails with smtp. The connection is secured with STARTTLS.
+pub struct EmailNotifier {
+    messages: Arc<Messages>,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    pub fn try_new(
+        messages: Arc<Messages>,
+        host: &str,
+        port: Option<u16>,
+        username: String,
+        password: String,
+        from: &str,
+        to: &[String],
+    ) -> anyhow::Result<EmailNotifier> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+            .port(port.unwrap_or(587))
+            .credentials(Credentials::new(username, password))
+            .build();
+
+        Ok(EmailNotifier {
+            messages,
+            transport,
+            from: from.parse()?,
+            to: to.iter().map(|address| address.parse()).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl TextNotifier for EmailNotifier {
+    fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    async fn send_text(&self, title: &str, text: &str) -> anyhow::Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(title)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+
+        self.transport.send(message.body(text.to_owned())?).await?;
+        Ok(())
+    }
+}
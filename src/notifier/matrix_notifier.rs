@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use async_trait::async_trait;
+use chrono::Utc;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::json;
+use crate::messages::Messages;
+use crate::notifier::TextNotifier;
+
+/// Sends plain text messages into a matrix room with the client-server api.
+pub struct MatrixNotifier {
+    messages: Arc<Messages>,
+    client: reqwest::Client,
+    homeserver: String,
+    access_token: String,
+    room_id: String,
+    transaction_counter: AtomicU64,
+}
+
+impl MatrixNotifier {
+    pub fn new(messages: Arc<Messages>, homeserver: String, access_token: String, room_id: String) -> MatrixNotifier {
+        MatrixNotifier {
+            messages,
+            client: reqwest::Client::new(),
+            homeserver: homeserver.trim_end_matches('/').to_owned(),
+            access_token,
+            room_id,
+            transaction_counter: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl TextNotifier for MatrixNotifier {
+    fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    async fn send_text(&self, title: &str, text: &str) -> anyhow::Result<()> {
+        // The homeserver deduplicates messages by transaction id, so it has to be unique per message
+        let transaction_id = format!(
+            "blitzer-{}-{}",
+            Utc::now().timestamp_micros(),
+            self.transaction_counter.fetch_add(1, Ordering::Relaxed)
+        );
+        // Room ids like !abc:example.org contain reserved characters
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.homeserver,
+            utf8_percent_encode(&self.room_id, NON_ALPHANUMERIC),
+            transaction_id
+        );
+
+        self.client
+            .put(url)
+            .bearer_auth(&self.access_token)
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": format!("{title}\n\n{text}"),
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::json;
+use crate::messages::Messages;
+use crate::notifier::TextNotifier;
+
+/// Publishes messages to a ntfy topic, e.g. https://ntfy.sh/my-blitzer-topic
+pub struct NtfyNotifier {
+    messages: Arc<Messages>,
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl NtfyNotifier {
+    pub fn new(messages: Arc<Messages>, url: String, token: Option<String>) -> NtfyNotifier {
+        NtfyNotifier {
+            messages,
+            client: reqwest::Client::new(),
+            url,
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl TextNotifier for NtfyNotifier {
+    fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    async fn send_text(&self, title: &str, text: &str) -> anyhow::Result<()> {
+        let mut request = self.client.post(&self.url).header("Title", title).body(text.to_owned());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Sends messages with the application token of a gotify server.
+pub struct GotifyNotifier {
+    messages: Arc<Messages>,
+    client: reqwest::Client,
+    url: String,
+    token: String,
+    priority: i32,
+}
+
+impl GotifyNotifier {
+    pub fn new(messages: Arc<Messages>, url: String, token: String, priority: Option<i32>) -> GotifyNotifier {
+        GotifyNotifier {
+            messages,
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_owned(),
+            token,
+            priority: priority.unwrap_or(5),
+        }
+    }
+}
+
+#[async_trait]
+impl TextNotifier for GotifyNotifier {
+    fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    async fn send_text(&self, title: &str, text: &str) -> anyhow::Result<()> {
+        self.client
+            .post(format!("{}/message", self.url))
+            .header("X-Gotify-Key", &self.token)
+            .json(&json!({
+                "title": title,
+                "message": text,
+                "priority": self.priority,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use teloxide::{ApiError, Bot, RequestError};
+use teloxide::payloads::{EditMessageTextSetters, SendMessageSetters};
+use teloxide::prelude::{ChatId, Requester};
+use teloxide::types::{LinkPreviewOptions, MessageId, ParseMode, Recipient};
+use crate::configuration::TelegramFormat;
+use crate::database::{KnownPoi, MessageIds, PoiChange};
+use crate::messages::{Messages, TextFormat};
+use crate::model::DetailedPoi;
+use crate::notifier::Notifier;
+use std::sync::Arc;
+
+/// Sends every poi as message, which is edited on changes and deleted once the poi is gone.
+/// Depending on the format the location is a separate message.
+pub struct TelegramNotifier {
+    bot: Bot,
+    messages: Arc<Messages>,
+    format: TelegramFormat,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot: Bot, messages: Arc<Messages>, format: TelegramFormat, chat_id: String) -> TelegramNotifier {
+        TelegramNotifier {
+            bot,
+            messages,
+            format,
+            chat_id,
+        }
+    }
+
+    fn text_format(&self) -> TextFormat {
+        match self.format {
+            TelegramFormat::Html => TextFormat::Html,
+            TelegramFormat::Text | TelegramFormat::Venue => TextFormat::Plain,
+        }
+    }
+
+    async fn send(&self, chat_id: Recipient, poi: &DetailedPoi, message: String) -> anyhow::Result<MessageIds> {
+        let message_ids = match self.format {
+            TelegramFormat::Text => {
+                let info_message = self.bot.send_message(chat_id.clone(), message).await?;
+                let location_message = match self.bot.send_location(chat_id, poi.lat, poi.lng).await {
+                    Ok(location_message) => location_message,
+                    Err(error) => {
+                        // Without the location the poi is sent again later, so the info must not stay behind
+                        self.delete_message(info_message.chat.id, info_message.id.0).await?;
+                        return Err(error.into());
+                    }
+                };
+                MessageIds {
+                    chat_id: info_message.chat.id.0,
+                    info: info_message.id.0,
+                    location: Some(location_message.id.0),
+                }
+            }
+            TelegramFormat::Html => {
+                let info_message = self
+                    .bot
+                    .send_message(chat_id, message)
+                    .parse_mode(ParseMode::Html)
+                    .link_preview_options(disabled_link_preview())
+                    .await?;
+                MessageIds {
+                    chat_id: info_message.chat.id.0,
+                    info: info_message.id.0,
+                    location: None,
+                }
+            }
+            TelegramFormat::Venue => {
+                let (title, address) = self.messages.venue(poi);
+                let venue_message = self.bot.send_venue(chat_id, poi.lat, poi.lng, title, address).await?;
+                MessageIds {
+                    chat_id: venue_message.chat.id.0,
+                    info: venue_message.id.0,
+                    location: None,
+                }
+            }
+        };
+
+        Ok(message_ids)
+    }
+
+    async fn delete(&self, message_ids: &MessageIds) -> Result<(), RequestError> {
+        let chat_id = ChatId(message_ids.chat_id);
+
+        self.delete_message(chat_id, message_ids.info).await?;
+        if let Some(location) = message_ids.location {
+            self.delete_message(chat_id, location).await?;
+        }
+        Ok(())
+    }
+
+    // A message which was already deleted by hand is no reason to fail. Telegram only allows bots to delete
+    // messages of the last 48 hours, older messages are kept.
+    async fn delete_message(&self, chat_id: ChatId, message_id: i32) -> Result<(), RequestError> {
+        match self.bot.delete_message(chat_id, MessageId(message_id)).await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageToDeleteNotFound)) => Ok(()),
+            Err(RequestError::Api(ApiError::MessageCantBeDeleted)) => {
+                println!("Message {} in chat {} can not be deleted anymore.. keeping it", message_id, chat_id);
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+fn disabled_link_preview() -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: true,
+        url: None,
+        prefer_small_media: false,
+        prefer_large_media: false,
+        show_above_text: false,
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    async fn send_new_poi(&self, _region: &str, poi: &DetailedPoi, reappeared: bool) -> anyhow::Result<Option<MessageIds>> {
+        let message = if reappeared {
+            self.messages.reappeared_poi(poi, self.text_format())
+        } else {
+            self.messages.new_poi(poi, self.text_format())
+        };
+
+        Ok(Some(self.send(Recipient::from(self.chat_id.clone()), poi, message).await?))
+    }
+
+    async fn send_changed_poi(
+        &self,
+        _region: &str,
+        known_poi: &KnownPoi,
+        poi: &DetailedPoi,
+        changes: &[PoiChange],
+    ) -> anyhow::Result<Option<MessageIds>> {
+        let Some(message_ids) = &known_poi.message_ids else {
+            return Ok(None);
+        };
+        let chat_id = ChatId(message_ids.chat_id);
+        let message = self.messages.changed_poi(poi, changes, self.text_format());
+
+        // A venue can not be edited, so it is sent again
+        if let TelegramFormat::Venue = self.format {
+            self.delete(message_ids).await?;
+            return Ok(Some(self.send(Recipient::Id(chat_id), poi, message).await?));
+        }
+
+        let mut edit_message = self.bot.edit_message_text(chat_id, MessageId(message_ids.info), message.clone());
+        if let TelegramFormat::Html = self.format {
+            edit_message = edit_message.parse_mode(ParseMode::Html).link_preview_options(disabled_link_preview());
+        }
+        match edit_message.await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(RequestError::Api(ApiError::MessageToEditNotFound)) => {
+                println!("Message {} of poi {} was deleted.. sending it again", message_ids.info, known_poi.backend_id);
+                self.delete(message_ids).await?;
+                return Ok(Some(self.send(Recipient::Id(chat_id), poi, message).await?));
+            }
+            Err(error) => return Err(error.into()),
+        }
+
+        let mut location = message_ids.location;
+        if let (Some(message_id_location), true) = (location, changes.iter().any(|change| change.is_movement())) {
+            // A location can only be edited while it is live, so the old one is replaced
+            self.delete_message(chat_id, message_id_location).await?;
+            location = Some(self.bot.send_location(chat_id, poi.lat, poi.lng).await?.id.0);
+        }
+
+        Ok(Some(MessageIds { location, ..message_ids.clone() }))
+    }
+
+    async fn retract_poi(&self, _region: &str, known_poi: &KnownPoi) -> anyhow::Result<()> {
+        match &known_poi.message_ids {
+            Some(message_ids) => Ok(self.delete(message_ids).await?),
+            None => Ok(()),
+        }
+    }
+
+    async fn send_error(&self, message: &str) -> anyhow::Result<()> {
+        self.bot.send_message(self.chat_id.clone(), message).await?;
+        Ok(())
+    }
+
+    async fn discard(&self, message_ids: &MessageIds) -> anyhow::Result<()> {
+        Ok(self.delete(message_ids).await?)
+    }
+}
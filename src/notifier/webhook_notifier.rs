@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use serde::Serialize;
+use sha2::Sha256;
+use crate::database::{KnownPoi, MessageIds, PoiChange};
+use crate::model::{Address, Category, DetailedPoi, Language, LocationType};
+use crate::notifier::{FailedDelivery, Notifier};
+
+// Increased on every incompatible change of the payload
+const PAYLOAD_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum WebhookEvent {
+    Appeared,
+    Reappeared,
+    Changed,
+    Disappeared,
+    Error,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    version: u32,
+    event: WebhookEvent,
+    sent_at: DateTime<Local>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    poi: Option<WebhookPoi<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changes: Vec<WebhookChange<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct WebhookPoi<'a> {
+    region: &'a str,
+    backend_id: &'a str,
+    lat: f64,
+    lng: f64,
+    address: &'a Address,
+    location_type: &'a str,
+    location_type_name: Option<&'static str>,
+    category: Category,
+    vmax: Option<i32>,
+    create_date: Option<NaiveDateTime>,
+    confirm_date: Option<NaiveDateTime>,
+    info: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct WebhookChange<'a> {
+    field: &'a str,
+    old_value: Option<&'a str>,
+    new_value: Option<&'a str>,
+}
+
+impl<'a> WebhookPoi<'a> {
+    fn from_poi(region: &'a str, poi: &'a DetailedPoi) -> WebhookPoi<'a> {
+        WebhookPoi {
+            region,
+            backend_id: &poi.backend,
+            lat: poi.lat,
+            lng: poi.lng,
+            address: &poi.address,
+            location_type: &poi.poi_type,
+            location_type_name: location_type_name(&poi.poi_type),
+            category: category(&poi.poi_type),
+            vmax: poi.vmax,
+            create_date: poi.create_date,
+            confirm_date: poi.confirm_date,
+            info: poi.info.desc.as_deref(),
+        }
+    }
+
+    fn from_known_poi(region: &'a str, known_poi: &'a KnownPoi) -> WebhookPoi<'a> {
+        WebhookPoi {
+            region,
+            backend_id: &known_poi.backend_id,
+            lat: known_poi.lat,
+            lng: known_poi.lng,
+            address: &known_poi.address,
+            location_type: &known_poi.poi_type,
+            location_type_name: location_type_name(&known_poi.poi_type),
+            category: category(&known_poi.poi_type),
+            vmax: known_poi.vmax,
+            create_date: known_poi.create_date,
+            confirm_date: known_poi.confirm_date,
+            info: known_poi.info_desc.as_deref(),
+        }
+    }
+}
+
+// The english name of the type metadata, it does not depend on the language of the messages
+fn location_type_name(code: &str) -> Option<&'static str> {
+    match LocationType::from_code(code) {
+        LocationType::Unknown(_) => None,
+        location_type => Some(location_type.display_name(Language::English)),
+    }
+}
+
+fn category(code: &str) -> Category {
+    LocationType::from_code(code).metadata().category
+}
+
+/// Posts every event as a stable json document. With a secret the body is signed with HMAC-SHA256
+/// in the `X-Blitzer-Signature` header.
+pub struct WebhookNotifier {
+    name: String,
+    client: ClientWithMiddleware,
+    url: String,
+    headers: HeaderMap,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn try_new(
+        name: String,
+        url: String,
+        headers: HashMap<String, String>,
+        secret: Option<String>,
+        retries: Option<u32>,
+    ) -> anyhow::Result<WebhookNotifier> {
+        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(retries.unwrap_or(3));
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.insert(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+        }
+
+        Ok(WebhookNotifier {
+            name,
+            client,
+            url,
+            headers: header_map,
+            secret,
+        })
+    }
+
+    async fn post(
+        &self,
+        event: WebhookEvent,
+        poi: Option<WebhookPoi<'_>>,
+        changes: &[PoiChange],
+        message: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let payload = WebhookPayload {
+            version: PAYLOAD_VERSION,
+            event,
+            sent_at: Local::now(),
+            poi,
+            changes: changes
+                .iter()
+                .map(|change| WebhookChange {
+                    field: change.field,
+                    old_value: change.old_value.as_deref(),
+                    new_value: change.new_value.as_deref(),
+                })
+                .collect(),
+            message,
+        };
+        let body = serde_json::to_string(&payload)?;
+
+        self.deliver(&body).await.map_err(|error| {
+            FailedDelivery {
+                notifier: self.name.clone(),
+                url: self.url.clone(),
+                payload: body,
+                error: error.to_string(),
+            }
+            .into()
+        })
+    }
+
+    async fn deliver(&self, body: &str) -> anyhow::Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_owned());
+
+        if let Some(secret) = &self.secret {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+            mac.update(body.as_bytes());
+            request = request.header("X-Blitzer-Signature", format!("sha256={}", hex::encode(mac.finalize().into_bytes())));
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send_new_poi(&self, region: &str, poi: &DetailedPoi, reappeared: bool) -> anyhow::Result<Option<MessageIds>> {
+        let event = if reappeared { WebhookEvent::Reappeared } else { WebhookEvent::Appeared };
+        self.post(event, Some(WebhookPoi::from_poi(region, poi)), &[], None).await?;
+        Ok(None)
+    }
+
+    async fn send_changed_poi(
+        &self,
+        region: &str,
+        _known_poi: &KnownPoi,
+        poi: &DetailedPoi,
+        changes: &[PoiChange],
+    ) -> anyhow::Result<Option<MessageIds>> {
+        self.post(WebhookEvent::Changed, Some(WebhookPoi::from_poi(region, poi)), changes, None).await?;
+        Ok(None)
+    }
+
+    async fn retract_poi(&self, region: &str, known_poi: &KnownPoi) -> anyhow::Result<()> {
+        self.post(WebhookEvent::Disappeared, Some(WebhookPoi::from_known_poi(region, known_poi)), &[], None).await
+    }
+
+    async fn send_error(&self, message: &str) -> anyhow::Result<()> {
+        self.post(WebhookEvent::Error, None, &[], Some(message)).await
+    }
+}
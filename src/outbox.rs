@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use crate::database::{Delivery, DeliveryEvent, DeliveryState, MessageIds, Storage};
+use crate::notifier::{FailedDelivery, Notifier};
+
+// A notification is identified by its poi, event, notifier and the changes it announces
+type DeliveryKey = (String, DeliveryEvent, String, String);
+
+/// The notifications of a region, stored as pending before they are sent, as sent afterwards and as persisted
+/// once the poi was written. A run which stopped in between resumes the sent notifications instead of sending
+/// them again. A notification which was still pending is sent again, since it is unknown whether it went out, so
+/// notifications are delivered at least once. Exactly once would need idempotent sends, which telegram does not offer.
+pub struct Outbox {
+    region: String,
+    deliveries: HashMap<DeliveryKey, Delivery>,
+    delivered: HashSet<DeliveryKey>,
+}
+
+impl Outbox {
+    pub fn load(database: &mut dyn Storage, region: &str) -> anyhow::Result<Outbox> {
+        let deliveries = database
+            .get_deliveries(region)?
+            .into_iter()
+            .map(|delivery| (key(&delivery.backend_id, delivery.event, &delivery.notifier, &delivery.details), delivery))
+            .collect::<HashMap<_, _>>();
+        if !deliveries.is_empty() {
+            println!("There are {} notifications of an earlier run in the outbox", deliveries.len());
+        }
+
+        Ok(Outbox {
+            region: region.to_owned(),
+            deliveries,
+            delivered: HashSet::new(),
+        })
+    }
+
+    /// Sends a notification, unless it was already sent by an earlier run which could not store the poi.
+    pub async fn deliver(
+        &mut self,
+        database: &mut dyn Storage,
+        backend_id: &str,
+        event: DeliveryEvent,
+        notifier: &str,
+        details: &str,
+        send: impl Future<Output = anyhow::Result<Option<MessageIds>>>,
+    ) -> anyhow::Result<Option<MessageIds>> {
+        let key = key(backend_id, event, notifier, details);
+        self.delivered.insert(key.clone());
+
+        let id = match self.deliveries.get(&key) {
+            Some(delivery) if delivery.state == DeliveryState::Sent => {
+                println!("Notification {} of poi {} was already sent by {}.. resuming", event, backend_id, notifier);
+                return Ok(delivery.message_ids.clone());
+            }
+            Some(delivery) => {
+                database.update_delivery(delivery.id, DeliveryState::Pending, None)?;
+                delivery.id
+            }
+            None => database.add_delivery(&self.region, backend_id, event, notifier, details)?,
+        };
+
+        let message_ids = keep_dead_letter(database, send.await)?;
+        database.update_delivery(id, DeliveryState::Sent, message_ids.as_ref())?;
+        self.deliveries.insert(
+            key,
+            Delivery {
+                id,
+                backend_id: backend_id.to_owned(),
+                event,
+                notifier: notifier.to_owned(),
+                details: details.to_owned(),
+                state: DeliveryState::Sent,
+                message_ids: message_ids.clone(),
+            },
+        );
+
+        Ok(message_ids)
+    }
+
+    /// Marks the sent notifications of a poi as persisted, after the poi was written.
+    pub fn persist(&mut self, database: &mut dyn Storage, backend_id: &str) -> anyhow::Result<()> {
+        database.persist_deliveries(&self.region, backend_id)?;
+        for delivery in self.deliveries.values_mut() {
+            if delivery.backend_id == backend_id && delivery.state == DeliveryState::Sent {
+                delivery.state = DeliveryState::Persisted;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cleans up the outbox at the end of a run. Notifications which failed in this run are kept for the next one.
+    /// Sent notifications which were not resumed belong to pois which are gone or changed in between, their
+    /// messages are discarded unless a stored poi refers to them.
+    pub async fn reconcile(
+        self,
+        database: &mut dyn Storage,
+        region_notifiers: &[(String, Arc<dyn Notifier>)],
+    ) -> anyhow::Result<()> {
+        let stored_message_ids = database
+            .get_known_pois(&self.region)?
+            .into_iter()
+            .filter_map(|known_poi| known_poi.message_ids)
+            .map(|message_ids| (message_ids.chat_id, message_ids.info))
+            .collect::<HashSet<_>>();
+
+        for (key, delivery) in self.deliveries {
+            let is_resumed = self.delivered.contains(&key);
+            match delivery.state {
+                DeliveryState::Persisted => {}
+                _ if is_resumed => continue,
+                DeliveryState::Pending => {}
+                DeliveryState::Sent => {
+                    let unstored_message_ids = delivery
+                        .message_ids
+                        .as_ref()
+                        .filter(|message_ids| !stored_message_ids.contains(&(message_ids.chat_id, message_ids.info)));
+                    let notifier = region_notifiers.iter().find(|(name, _)| *name == delivery.notifier);
+                    if let (Some(message_ids), Some((_, notifier))) = (unstored_message_ids, notifier) {
+                        println!(
+                            "Notification {} of poi {} was never persisted.. discarding its messages",
+                            delivery.event, delivery.backend_id
+                        );
+                        if let Err(error) = notifier.discard(message_ids).await {
+                            eprintln!("Failed to discard messages {:?}: {}.. retrying it in the next run", message_ids, error);
+                            continue;
+                        }
+                    }
+                }
+            }
+            database.delete_delivery(delivery.id)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn key(backend_id: &str, event: DeliveryEvent, notifier: &str, details: &str) -> DeliveryKey {
+    (backend_id.to_owned(), event, notifier.to_owned(), details.to_owned())
+}
+
+fn keep_dead_letter<T: Default>(database: &mut dyn Storage, result: anyhow::Result<T>) -> anyhow::Result<T> {
+    match result {
+        Err(error) => match error.downcast::<FailedDelivery>() {
+            Ok(failed_delivery) => {
+                eprintln!("{}.. keeping it as dead letter", failed_delivery);
+                database.add_dead_letter(
+                    &failed_delivery.notifier,
+                    &failed_delivery.url,
+                    &failed_delivery.payload,
+                    &failed_delivery.error,
+                )?;
+                Ok(T::default())
+            }
+            Err(error) => Err(error),
+        },
+        result => result,
+    }
+}
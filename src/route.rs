@@ -0,0 +1,412 @@
+use crate::model::{Coordinate, LocationBox};
+
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+pub struct Route {
+    // Separate lines of the route, e.g. the segments of a gpx track, which are not connected to each other
+    lines: Vec<Vec<Coordinate>>,
+    buffer_meters: f64,
+    max_box_size_meters: f64,
+}
+
+impl Route {
+    /// Rejects sizes which would cover the route with endless boxes or shrink the boxes below the route.
+    pub fn try_new(lines: Vec<Vec<Coordinate>>, buffer_meters: f64, max_box_size_meters: f64) -> anyhow::Result<Route> {
+        if max_box_size_meters.is_nan() || max_box_size_meters <= 0.0 {
+            anyhow::bail!("The max box size should be greater than 0, got {} m", max_box_size_meters);
+        }
+        if buffer_meters.is_nan() || buffer_meters < 0.0 {
+            anyhow::bail!("The buffer should be at least 0, got {} m", buffer_meters);
+        }
+
+        Ok(Route {
+            lines,
+            buffer_meters,
+            max_box_size_meters,
+        })
+    }
+
+    /// Collects the track and route points of a gpx file. Every track segment and route is a separate line,
+    /// way points are standalone points and are ignored.
+    pub fn parse_gpx(gpx: &str) -> anyhow::Result<Vec<Vec<Coordinate>>> {
+        let mut lines: Vec<Vec<Coordinate>> = Vec::new();
+
+        for (index, _) in gpx.match_indices('<') {
+            let tag = &gpx[index + 1..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let name = tag.split(|character: char| character.is_whitespace() || character == '/').next().unwrap_or("");
+            // Ignores namespace prefixes like <gpx:trkpt>
+            let name = name.rsplit(':').next().unwrap_or(name);
+
+            match name {
+                "trkseg" | "rte" => lines.push(Vec::new()),
+                "trkpt" | "rtept" => {
+                    let (Some(lat), Some(lon)) = (gpx_attribute(tag, "lat"), gpx_attribute(tag, "lon")) else {
+                        anyhow::bail!("Gpx point without lat/lon attribute: <{}>", tag);
+                    };
+                    let point = Coordinate {
+                        lat: lat.trim().parse()?,
+                        lng: lon.trim().parse()?,
+                    };
+
+                    match lines.last_mut() {
+                        Some(line) => line.push(point),
+                        None => lines.push(vec![point]),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        lines.retain(|line| !line.is_empty());
+        if lines.is_empty() {
+            anyhow::bail!("Gpx file does not contain any track or route points");
+        }
+
+        Ok(lines)
+    }
+
+    /// Decodes an encoded polyline (precision 5) as used by Google Maps, OSRM and others.
+    pub fn parse_polyline(polyline: &str) -> anyhow::Result<Vec<Coordinate>> {
+        let mut points = Vec::new();
+        let mut bytes = polyline.trim().bytes();
+        let mut lat = 0i64;
+        let mut lng = 0i64;
+
+        loop {
+            let Some(lat_delta) = next_polyline_value(&mut bytes)? else {
+                break;
+            };
+            let Some(lng_delta) = next_polyline_value(&mut bytes)? else {
+                anyhow::bail!("Polyline ends in the middle of a coordinate");
+            };
+
+            lat += lat_delta;
+            lng += lng_delta;
+            points.push(Coordinate {
+                lat: lat as f64 / 1e5,
+                lng: lng as f64 / 1e5,
+            });
+        }
+
+        if points.is_empty() {
+            anyhow::bail!("Polyline does not contain any points");
+        }
+
+        Ok(points)
+    }
+
+    /// Returns the boxes which have to be requested to cover the whole corridor around the route.
+    /// Consecutive points are merged into one box as long as the box stays below the configured size.
+    pub fn location_boxes(&self) -> Vec<LocationBox> {
+        let mut boxes: Vec<LocationBox> = Vec::new();
+
+        for line in &self.lines {
+            let mut current: Option<LocationBox> = None;
+            let mut previous: Option<&Coordinate> = None;
+
+            let points = self.densified_points(line);
+            for point in &points {
+                current = match (current, previous) {
+                    (Some(location_box), Some(previous)) => {
+                        let extended = extend_box(&location_box, point);
+                        if diagonal_meters(&extended) > self.max_box_size_meters {
+                            boxes.push(location_box);
+                            // The next box starts at the previous point, so the segment in between stays covered
+                            Some(extend_box(&point_box(previous), point))
+                        } else {
+                            Some(extended)
+                        }
+                    }
+                    _ => Some(point_box(point)),
+                };
+                previous = Some(point);
+            }
+
+            boxes.extend(current);
+        }
+
+        boxes.iter().map(|location_box| self.with_buffer(location_box)).collect()
+    }
+
+    /// Splits long segments, so that every segment fits into a single box.
+    fn densified_points(&self, line: &[Coordinate]) -> Vec<Coordinate> {
+        let max_segment_length = self.max_box_size_meters / 2.0;
+        let mut points: Vec<Coordinate> = Vec::new();
+
+        for point in line {
+            if let Some(previous) = points.last().cloned() {
+                let parts = (distance_meters(&previous, point) / max_segment_length).ceil().max(1.0) as usize;
+                for part in 1..parts {
+                    let fraction = part as f64 / parts as f64;
+                    points.push(Coordinate {
+                        lat: previous.lat + (point.lat - previous.lat) * fraction,
+                        lng: previous.lng + (point.lng - previous.lng) * fraction,
+                    });
+                }
+            }
+            points.push(point.clone());
+        }
+
+        points
+    }
+
+    /// Checks whether the coordinate is within the buffer distance of any segment of the route.
+    pub fn contains(&self, lat: f64, lng: f64) -> bool {
+        let point = Coordinate { lat, lng };
+
+        self.lines.iter().any(|line| {
+            if line.len() == 1 {
+                return distance_to_segment(&point, &line[0], &line[0]) <= self.buffer_meters;
+            }
+
+            line.windows(2)
+                .any(|segment| distance_to_segment(&point, &segment[0], &segment[1]) <= self.buffer_meters)
+        })
+    }
+
+    fn with_buffer(&self, location_box: &LocationBox) -> LocationBox {
+        let lat_buffer = (self.buffer_meters / EARTH_RADIUS_METERS).to_degrees();
+        let center_lat = ((location_box.lat_min + location_box.lat_max) / 2.0).to_radians();
+        let lng_buffer = lat_buffer / center_lat.cos().max(0.01);
+
+        LocationBox {
+            lat_min: location_box.lat_min - lat_buffer,
+            lng_min: location_box.lng_min - lng_buffer,
+            lat_max: location_box.lat_max + lat_buffer,
+            lng_max: location_box.lng_max + lng_buffer,
+        }
+    }
+}
+
+// Attributes may be separated by any whitespace and may have whitespace around the equals sign
+fn gpx_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+
+    while let Some(equals) = rest.find('=') {
+        let attribute_name = rest[..equals].trim_end().rsplit(char::is_whitespace).next().unwrap_or("");
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next().filter(|quote| *quote == '"' || *quote == '\'')?;
+        let value = &value[1..];
+        let end = value.find(quote)?;
+
+        if attribute_name == name {
+            return Some(&value[..end]);
+        }
+        rest = &value[end + 1..];
+    }
+
+    None
+}
+
+fn next_polyline_value(bytes: &mut impl Iterator<Item = u8>) -> anyhow::Result<Option<i64>> {
+    let mut result = 0i64;
+    let mut shift = 0;
+
+    loop {
+        let Some(byte) = bytes.next() else {
+            if shift == 0 {
+                return Ok(None);
+            }
+            anyhow::bail!("Polyline ends in the middle of a value");
+        };
+        if !(63..127).contains(&byte) || shift > 60 {
+            anyhow::bail!("Invalid character in polyline: {}", byte as char);
+        }
+
+        let chunk = (byte - 63) as i64;
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+
+        if chunk < 0x20 {
+            break;
+        }
+    }
+
+    Ok(Some(if result & 1 == 1 { !(result >> 1) } else { result >> 1 }))
+}
+
+fn point_box(point: &Coordinate) -> LocationBox {
+    LocationBox {
+        lat_min: point.lat,
+        lng_min: point.lng,
+        lat_max: point.lat,
+        lng_max: point.lng,
+    }
+}
+
+fn extend_box(location_box: &LocationBox, point: &Coordinate) -> LocationBox {
+    LocationBox {
+        lat_min: location_box.lat_min.min(point.lat),
+        lng_min: location_box.lng_min.min(point.lng),
+        lat_max: location_box.lat_max.max(point.lat),
+        lng_max: location_box.lng_max.max(point.lng),
+    }
+}
+
+fn diagonal_meters(location_box: &LocationBox) -> f64 {
+    distance_meters(
+        &Coordinate { lat: location_box.lat_min, lng: location_box.lng_min },
+        &Coordinate { lat: location_box.lat_max, lng: location_box.lng_max },
+    )
+}
+
+/// Great circle distance (haversine) between two coordinates.
+pub fn distance_meters(from: &Coordinate, to: &Coordinate) -> f64 {
+    let delta_lat = (to.lat - from.lat).to_radians();
+    let delta_lng = (to.lng - from.lng).to_radians();
+
+    let a = (delta_lat / 2.0).sin().powi(2)
+        + from.lat.to_radians().cos() * to.lat.to_radians().cos() * (delta_lng / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+/// Initial bearing from one coordinate to another in degrees, clockwise from north.
+pub fn bearing_degrees(from: &Coordinate, to: &Coordinate) -> f64 {
+    let delta_lng = (to.lng - from.lng).to_radians();
+    let (from_lat, to_lat) = (from.lat.to_radians(), to.lat.to_radians());
+
+    let y = delta_lng.sin() * to_lat.cos();
+    let x = from_lat.cos() * to_lat.sin() - from_lat.sin() * to_lat.cos() * delta_lng.cos();
+
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Distance between a point and a segment. The segment is projected onto a local plane around
+/// the point, which is precise enough for the short segments of a gpx track.
+fn distance_to_segment(point: &Coordinate, start: &Coordinate, end: &Coordinate) -> f64 {
+    let scale_lng = point.lat.to_radians().cos();
+    let project = |coordinate: &Coordinate| {
+        (
+            (coordinate.lng - point.lng).to_radians() * scale_lng * EARTH_RADIUS_METERS,
+            (coordinate.lat - point.lat).to_radians() * EARTH_RADIUS_METERS,
+        )
+    };
+
+    let (start_x, start_y) = project(start);
+    let (end_x, end_y) = project(end);
+    let (delta_x, delta_y) = (end_x - start_x, end_y - start_y);
+    let length_squared = delta_x * delta_x + delta_y * delta_y;
+
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (-(start_x * delta_x + start_y * delta_y) / length_squared).clamp(0.0, 1.0)
+    };
+
+    let (closest_x, closest_y) = (start_x + t * delta_x, start_y + t * delta_y);
+    (closest_x * closest_x + closest_y * closest_y).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinate(lat: f64, lng: f64) -> Coordinate {
+        Coordinate { lat, lng }
+    }
+
+    fn assert_points(actual: &[Coordinate], expected: &[(f64, f64)]) {
+        assert_eq!(actual.len(), expected.len());
+        for (point, (lat, lng)) in actual.iter().zip(expected) {
+            assert!((point.lat - lat).abs() < 1e-6, "{} != {}", point.lat, lat);
+            assert!((point.lng - lng).abs() < 1e-6, "{} != {}", point.lng, lng);
+        }
+    }
+
+    #[test]
+    fn parses_polyline() {
+        let points = Route::parse_polyline("_p~iF~ps|U_ulLnnqC_mqNvxq`@").unwrap();
+
+        assert_points(&points, &[(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)]);
+    }
+
+    #[test]
+    fn rejects_truncated_polyline() {
+        assert!(Route::parse_polyline("_p~iF~ps|U_").is_err());
+    }
+
+    #[test]
+    fn parses_gpx_segments_without_waypoints() {
+        let gpx = r#"<?xml version="1.0"?>
+            <gpx version="1.1">
+              <wpt lat="10.0" lon="10.0"><name>Home</name></wpt>
+              <trk>
+                <trkseg>
+                  <trkpt lat="50.0" lon="8.0"/>
+                  <trkpt lat="50.1" lon="8.1"></trkpt>
+                </trkseg>
+                <trkseg>
+                  <trkpt lon="9.0" lat="51.0"/>
+                </trkseg>
+              </trk>
+              <rte><rtept lat="52.0" lon="7.0"/></rte>
+            </gpx>"#;
+
+        let lines = Route::parse_gpx(gpx).unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert_points(&lines[0], &[(50.0, 8.0), (50.1, 8.1)]);
+        assert_points(&lines[1], &[(51.0, 9.0)]);
+        assert_points(&lines[2], &[(52.0, 7.0)]);
+    }
+
+    #[test]
+    fn parses_gpx_attributes_tolerantly() {
+        let gpx = "<gpx:trkseg><gpx:trkpt\n\tlat = '50.5'\n\tlon=\"8.5\" ele=\"100\"/></gpx:trkseg>";
+
+        let lines = Route::parse_gpx(gpx).unwrap();
+
+        assert_eq!(lines.len(), 1);
+        assert_points(&lines[0], &[(50.5, 8.5)]);
+    }
+
+    #[test]
+    fn rejects_gpx_without_track_points() {
+        assert!(Route::parse_gpx(r#"<gpx><wpt lat="10.0" lon="10.0"/></gpx>"#).is_err());
+        assert!(Route::parse_gpx(r#"<gpx><trkseg><trkpt lat="10.0"/></trkseg></gpx>"#).is_err());
+    }
+
+    #[test]
+    fn contains_points_within_buffer() {
+        let route = Route::try_new(
+            vec![vec![coordinate(50.0, 8.0), coordinate(50.0, 8.1)], vec![coordinate(51.0, 9.0)]],
+            100.0,
+            25_000.0,
+        )
+        .unwrap();
+
+        assert!(route.contains(50.0005, 8.05));
+        assert!(!route.contains(50.01, 8.05));
+        assert!(route.contains(51.0005, 9.0));
+        // The gap between the two lines is not part of the route
+        assert!(!route.contains(50.5, 8.55));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        let lines = || vec![vec![coordinate(50.0, 8.0), coordinate(50.0, 8.1)]];
+
+        assert!(Route::try_new(lines(), 100.0, 0.0).is_err());
+        assert!(Route::try_new(lines(), 100.0, -1000.0).is_err());
+        assert!(Route::try_new(lines(), 100.0, f64::NAN).is_err());
+        assert!(Route::try_new(lines(), -1.0, 25_000.0).is_err());
+        assert!(Route::try_new(lines(), f64::NAN, 25_000.0).is_err());
+        assert!(Route::try_new(lines(), 0.0, 25_000.0).is_ok());
+    }
+
+    #[test]
+    fn measures_distance_to_segment() {
+        let start = coordinate(50.0, 8.0);
+        let end = coordinate(50.0, 8.1);
+
+        let beside = distance_to_segment(&coordinate(50.001, 8.05), &start, &end);
+        assert!((beside - distance_meters(&coordinate(50.001, 8.05), &coordinate(50.0, 8.05))).abs() < 1.0);
+
+        let behind = distance_to_segment(&coordinate(50.0, 7.9), &start, &end);
+        assert!((behind - distance_meters(&coordinate(50.0, 7.9), &start)).abs() < 1.0);
+
+        assert!(distance_to_segment(&start, &start, &start) < 1e-6);
+    }
+}
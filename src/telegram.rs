@@ -1,8 +1,5 @@
 use crate::configuration::{get_telegram_bot_configuration};
 use teloxide::Bot;
-use teloxide::prelude::{ChatId, Message};
-use teloxide::requests::Requester;
-use teloxide::types::MessageId;
 
 pub struct TelegramBot {
     bot: Bot,
@@ -15,19 +12,12 @@ impl TelegramBot {
         let bot = Bot::new(bot_configuration.token);
         TelegramBot { bot , chat_id: bot_configuration.chat_id}
     }
-    
-    pub async fn send_message<T>(&self, message: T) -> Message 
-        where T: Into<String> {
-        self.bot.send_message(self.chat_id.clone(), message).await.expect("Should send message")
-    }
-    
-    pub async fn send_location(&self, latitude: f64, longitude: f64) -> Message {
-        self.bot.send_location(self.chat_id.clone(), latitude, longitude).await.expect("Should send location")
+
+    pub fn bot(&self) -> Bot {
+        self.bot.clone()
     }
-    
-    pub async fn delete_message(&self, chat_id: i64, message_id_info: i32, message_id_location: i32) {
-        self.bot.delete_message(ChatId(chat_id), MessageId(message_id_info)).await.expect("Should delete info message");
-        self.bot.delete_message(ChatId(chat_id), MessageId(message_id_location)).await.expect("Should delete location message");
+
+    pub fn chat_id(&self) -> &str {
+        &self.chat_id
     }
 }
-